@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
 use std::cmp;
 use std::convert::TryFrom;
@@ -5,30 +6,54 @@ use std::fs;
 use std::io::prelude::*;
 use std::io::{self, SeekFrom};
 use std::marker;
-use std::path::Path;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
 
-use crate::entry::{EntryFields, EntryIo};
+use crate::entry::{EntryFields, EntryInfo, EntryIo};
 use crate::error::TarError;
-use crate::header::{SparseEntry, BLOCK_SIZE};
+use crate::header::{bytes2path, SparseEntry, BLOCK_SIZE};
 use crate::other;
-use crate::pax::pax_extensions_size;
-use crate::{Entry, GnuExtSparseHeader, Header};
+use crate::pax::{pax_extensions_size, PaxExtensions};
+use crate::{Builder, Entry, EntryType, GnuExtSparseHeader, Header};
 
 /// A top-level representation of an archive file.
 ///
 /// This archive can have an entry added to it and it can be iterated over.
+///
+/// The archive keeps track of its own position in the underlying reader,
+/// counted from where the reader was when the archive was created, and
+/// assumes that nothing else moves it. When iterating with
+/// `entries_with_seek`, the reader is seeked to the offset of each header
+/// before reading it, so a reader which is shared with other code may be
+/// moved between entries. If it may also be moved while the contents of an
+/// entry are being read, see `set_always_seek`. Otherwise the archive must
+/// be the only user of the reader.
 pub struct Archive<R: ?Sized + Read> {
     inner: ArchiveInner<R>,
 }
 
 pub struct ArchiveInner<R: ?Sized> {
     pos: Cell<u64>,
+    start: u64,
     unpack_xattrs: bool,
     preserve_permissions: bool,
     preserve_ownerships: bool,
     preserve_mtime: bool,
     overwrite: bool,
     ignore_zeros: bool,
+    lenient_checksums: bool,
+    reject_empty_paths: bool,
+    max_entries: Option<u64>,
+    max_path_len: usize,
+    extract_newer_than: Option<u64>,
+    atomic_unpack: bool,
+    always_seek: bool,
+    cancel: Option<Arc<AtomicBool>>,
+    base: Cell<Option<u64>>,
     obj: RefCell<R>,
 }
 
@@ -38,7 +63,161 @@ pub struct Entries<'a, R: 'a + Read> {
     _ignored: marker::PhantomData<&'a Archive<R>>,
 }
 
-trait SeekRead: Read + Seek {}
+/// An iterator over the entries of an archive which stops at the first error.
+///
+/// This is created by the `try_entries` method of `Archive`. The error which
+/// ended iteration, if any, can be retrieved afterwards with `error` or
+/// `take_error`.
+pub struct TryEntries<'a, R: 'a + Read> {
+    entries: Entries<'a, R>,
+    error: Option<io::Error>,
+}
+
+/// An iterator over the path names of the entries of an archive.
+///
+/// This is created by the `names` and `names_with_seek` methods of `Archive`.
+pub struct Names<'a, R: 'a + Read> {
+    fields: EntriesFields<'a>,
+    _ignored: marker::PhantomData<&'a Archive<R>>,
+}
+
+/// An iterator over the entries of an archive which are accepted by a
+/// predicate.
+///
+/// This is created by the `scan` method of `Archive`.
+pub struct Scan<'a, R: 'a + Read, F> {
+    entries: Entries<'a, R>,
+    want: F,
+}
+
+/// A reader over the contents of consecutive entries, one after another.
+///
+/// This is created by the `concat` method of `Entries`.
+pub struct Concat<'b, 'a: 'b, R: 'a + Read, F> {
+    entries: &'b mut Entries<'a, R>,
+    group: F,
+    current: Option<Entry<'a, R>>,
+    next: Option<Entry<'a, R>>,
+    done: bool,
+}
+
+/// An entry which keeps the iterator that yielded it borrowed.
+///
+/// This is created by the `next_borrowed` method of `Entries`, and
+/// dereferences to the `Entry` itself.
+pub struct BorrowedEntry<'b, 'a: 'b, R: 'a + Read> {
+    entry: Entry<'a, R>,
+    _entries: marker::PhantomData<&'b mut Entries<'a, R>>,
+}
+
+/// A reader which writes every byte read through it to a hasher.
+///
+/// This is created by the `hashing_reader` method of `Archive`.
+pub struct HashingReader<R, H> {
+    inner: R,
+    hasher: H,
+}
+
+// private implementation detail of `EntryIo`, reading part of an entry's
+// contents at a known position of a seekable archive.
+pub struct SeekData<'a> {
+    archive: &'a ArchiveInner<dyn SeekRead + 'a>,
+    pos: u64,
+    left: u64,
+}
+
+/// The overall state of an archive, as returned by `Archive::status`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum ArchiveStatus {
+    /// The archive has no entries, only a terminating zero block.
+    Empty,
+    /// The archive has at least one entry, every entry is complete, and the
+    /// last one is followed by a terminating zero block.
+    Complete,
+    /// The input ends before a terminating zero block is found, whether
+    /// between entries or in the middle of a header or an entry's contents.
+    /// An input with no data at all is also considered truncated.
+    Truncated,
+}
+
+/// The format features which an archive makes use of, as returned by
+/// `Archive::features_used`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct FeatureSet {
+    /// GNU long name or long link name entries are present.
+    pub gnu_long_names: bool,
+    /// Pax extended headers, either local or global, are present.
+    pub pax: bool,
+    /// Some entry is sparse, in either the GNU or one of the pax formats.
+    pub sparse: bool,
+    /// Some numeric header field uses the GNU base-256 encoding rather than
+    /// octal, as is needed for example for entries of 8GB or more.
+    pub base256: bool,
+    /// Character or block device nodes are present.
+    pub device_nodes: bool,
+    /// GNU multi-volume continuation entries or volume labels are present.
+    pub multi_volume: bool,
+}
+
+/// An iterator over the entries of an in-memory archive along with their
+/// contents as slices of it.
+///
+/// This is created by the `entries_with_slices` method of `Archive`.
+pub struct SliceEntries<'a, 'x> {
+    entries: Entries<'a, io::Cursor<&'x [u8]>>,
+    data: &'x [u8],
+    base: u64,
+}
+
+/// An iterator over the entries of a seekable archive which owns the
+/// archive.
+///
+/// This is created by the `into_entries` method of `Archive`. The archive is
+/// shared through an `Rc` between the iterator and every `OwnedEntry` it
+/// yields, so entries may outlive the iterator and be read in any order:
+/// each read first seeks the underlying reader back to where that entry
+/// left off. Because the reader itself is shared, only one entry can be
+/// reading at any moment, and neither the iterator nor its entries can be
+/// sent to another thread.
+pub struct IntoEntries<R: Read + Seek> {
+    archive: Rc<Archive<R>>,
+    next: u64,
+    done: bool,
+    terminator: Option<u64>,
+    count: u64,
+}
+
+/// An entry yielded by `IntoEntries`, holding its own handle to the archive.
+///
+/// The contents of the entry can be read through the `Read` implementation.
+pub struct OwnedEntry<R: Read + Seek> {
+    archive: Rc<Archive<R>>,
+    header: Header,
+    path: Vec<u8>,
+    link_name: Option<Vec<u8>>,
+    size: u64,
+    segments: Vec<Segment>,
+    segment: usize,
+}
+
+enum Segment {
+    Pad(u64),
+    Data { pos: u64, len: u64 },
+}
+
+/// The default limit on the length of paths and link names of entries.
+const DEFAULT_MAX_PATH_LEN: usize = 64 * 1024;
+
+/// Room allowed in a pax extension entry for records other than `path` and
+/// `linkpath`, such as times, ids and extended attributes.
+const PAX_EXTENSIONS_SLACK: u64 = 64 * 1024;
+
+/// Decides whether `_unpack` extracts an entry.
+type UnpackFilter<'f> = dyn FnMut(&Entry<'_, io::Empty>) -> io::Result<bool> + 'f;
+
+pub trait SeekRead: Read + Seek {}
 impl<R: Read + Seek> SeekRead for R {}
 
 struct EntriesFields<'a> {
@@ -47,6 +226,8 @@ struct EntriesFields<'a> {
     next: u64,
     done: bool,
     raw: bool,
+    terminator: Option<u64>,
+    count: u64,
 }
 
 impl<R: Read> Archive<R> {
@@ -60,8 +241,18 @@ impl<R: Read> Archive<R> {
                 preserve_mtime: true,
                 overwrite: true,
                 ignore_zeros: false,
+                lenient_checksums: false,
+                reject_empty_paths: false,
+                max_entries: None,
+                max_path_len: DEFAULT_MAX_PATH_LEN,
+                extract_newer_than: None,
+                atomic_unpack: false,
+                always_seek: false,
+                cancel: None,
+                base: Cell::new(None),
                 obj: RefCell::new(obj),
                 pos: Cell::new(0),
+                start: 0,
             },
         }
     }
@@ -71,6 +262,47 @@ impl<R: Read> Archive<R> {
         self.inner.obj.into_inner()
     }
 
+    /// Wraps the underlying reader of this archive so that every byte read
+    /// from it is also written to `hasher`.
+    ///
+    /// This computes a digest of the archive stream as a side effect of
+    /// iterating over or unpacking it, avoiding a separate pass over the
+    /// data. Any type implementing `Write` can be used, which includes the
+    /// hashers of most digest crates. All options set on this archive are
+    /// carried over.
+    ///
+    /// Only bytes which are actually read are hashed. Iteration stops at the
+    /// end-of-archive marker, so any data after it is left unread; use
+    /// `into_inner` and `HashingReader::into_parts` to drain the rest of the
+    /// stream into the hasher if a digest of the whole input is required.
+    /// Seeking is not supported, as skipped bytes would be missed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use tar::Archive;
+    ///
+    /// let ar = Archive::new(File::open("foo.tar").unwrap());
+    /// let mut ar = ar.hashing_reader(Vec::new());
+    /// ar.unpack("foo").unwrap();
+    /// let (_file, copy) = ar.into_inner().into_parts();
+    /// ```
+    pub fn hashing_reader<H: Write>(self, hasher: H) -> Archive<HashingReader<R, H>> {
+        Archive {
+            inner: self.inner.map_obj(|inner| HashingReader { inner, hasher }),
+        }
+    }
+
+    /// Returns the offset in the underlying object at which this archive
+    /// starts.
+    ///
+    /// This is always 0 unless the archive was opened with
+    /// `Archive::open_scanning`.
+    pub fn start_offset(&self) -> u64 {
+        self.inner.start
+    }
+
     /// Construct an iterator over the entries in this archive.
     ///
     /// Note that care must be taken to consider each entry within an archive in
@@ -85,6 +317,171 @@ impl<R: Read> Archive<R> {
         })
     }
 
+    /// Construct an iterator over the entries in this archive which yields
+    /// entries directly rather than results.
+    ///
+    /// Iteration stops at the first error encountered, which is then stored
+    /// in the iterator so it can be checked once the loop is done. This is
+    /// convenient for the common case of failing on any error; use `entries`
+    /// for finer grained control.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use tar::Archive;
+    ///
+    /// let mut ar = Archive::new(File::open("foo.tar").unwrap());
+    /// let mut entries = ar.try_entries().unwrap();
+    /// for entry in &mut entries {
+    ///     println!("{}", entry.path().unwrap().display());
+    /// }
+    /// if let Some(e) = entries.take_error() {
+    ///     panic!("failed to read archive: {}", e);
+    /// }
+    /// ```
+    pub fn try_entries(&mut self) -> io::Result<TryEntries<'_, R>> {
+        self.entries().map(|entries| TryEntries {
+            entries,
+            error: None,
+        })
+    }
+
+    /// Returns the first entry of this archive, or `None` if it is empty.
+    ///
+    /// Only the header of the first entry (along with any long name or pax
+    /// extension entries describing it) is read, and the returned entry is
+    /// positioned at the start of its contents. This is useful for formats
+    /// which place a signature or manifest at the front of the archive.
+    ///
+    /// Like `entries`, this can only be called while the archive is at its
+    /// starting position.
+    pub fn first(&mut self) -> io::Result<Option<Entry<'_, R>>> {
+        self.entries()?.next().transpose()
+    }
+
+    /// Counts the entries of this archive for which `pred` returns `true`.
+    ///
+    /// This is useful for reporting, such as counting the directories or
+    /// executables in an archive. The contents of entries are skipped over
+    /// to reach the next header rather than being handed to `pred`, and the
+    /// limit set by `set_max_entries` is respected.
+    pub fn count_matching<F>(&mut self, mut pred: F) -> io::Result<usize>
+    where
+        F: FnMut(&Entry<'_, R>) -> bool,
+    {
+        let mut count = 0;
+        for entry in self.entries()? {
+            if pred(&entry?) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Reads through this archive to determine whether it is empty, complete
+    /// or truncated.
+    ///
+    /// See `ArchiveStatus` for the exact meaning of each state. This is meant
+    /// for sanity checks on produced artifacts, such as in CI. Every byte of
+    /// the archive up to the terminator is read, so an entry missing part of
+    /// its contents is reported as `Truncated`. Any other problem, such as a
+    /// corrupt header, is returned as an error. The first zero block always
+    /// counts as the terminator here, even if `set_ignore_zeros` is enabled.
+    pub fn status(&mut self) -> io::Result<ArchiveStatus> {
+        let me: &mut Archive<dyn Read> = self;
+        me._status()
+    }
+
+    /// Reads through this archive to determine which non-trivial format
+    /// features it uses.
+    ///
+    /// Both the entries and the GNU and pax extension entries which are
+    /// folded into the entries they describe are taken into account, and the
+    /// contents of entries are skipped over. This helps to tell, for example,
+    /// why extracting an archive behaves differently from another
+    /// implementation, or whether a simpler consumer can handle it. See
+    /// `FeatureSet` for the features detected.
+    pub fn features_used(&mut self) -> io::Result<FeatureSet> {
+        let me: &mut Archive<dyn Read> = self;
+        me._features_used()
+    }
+
+    /// Gathers the metadata of every entry in this archive for a verbose
+    /// listing, like `tar -tv`, in a single pass.
+    ///
+    /// See `Entry::info` for the details of each item; the contents of the
+    /// entries are skipped over.
+    pub fn verbose_list(&mut self) -> io::Result<Vec<EntryInfo>> {
+        self.entries()?.map(|entry| entry?.info()).collect()
+    }
+
+    /// Feeds the content of every entry in this archive to `hasher`,
+    /// returning it once the whole archive has been read.
+    ///
+    /// Only the following contribute to the digest, for each entry in order:
+    ///
+    /// * the path, as returned by `Entry::path_bytes`,
+    /// * the entry type byte,
+    /// * the link name, if any, as returned by `Entry::link_name_bytes`, and
+    /// * the contents of the entry.
+    ///
+    /// Every field is preceded by its length as a little-endian `u64` (the
+    /// link name by `u64::MAX` if there is none) so the digest can't be
+    /// forged by shifting bytes between fields. Metadata such as the mode,
+    /// owners and timestamps is ignored, as are pax global headers, so two
+    /// archives with the same content but, say, different mtimes produce the
+    /// same digest. Contents are streamed through `hasher` rather than being
+    /// loaded into memory.
+    pub fn content_digest<H: Write>(&mut self, mut hasher: H) -> io::Result<H> {
+        for entry in self.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_pax_global_extensions() {
+                continue;
+            }
+            let path = entry.path_bytes().into_owned();
+            hasher.write_all(&(path.len() as u64).to_le_bytes())?;
+            hasher.write_all(&path)?;
+            hasher.write_all(&[entry.header().entry_type().as_byte()])?;
+            match entry.link_name_bytes() {
+                Some(link) => {
+                    hasher.write_all(&(link.len() as u64).to_le_bytes())?;
+                    hasher.write_all(&link)?;
+                }
+                None => hasher.write_all(&u64::MAX.to_le_bytes())?,
+            }
+            // Hard links never have contents, whatever their size says.
+            let size = if entry.header().entry_type().is_hard_link() {
+                0
+            } else {
+                entry.size()
+            };
+            hasher.write_all(&size.to_le_bytes())?;
+            if io::copy(&mut entry, &mut hasher)? != size {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "archive ended in the middle of an entry",
+                ));
+            }
+        }
+        Ok(hasher)
+    }
+
+    /// Construct an iterator over the path names of the entries in this
+    /// archive.
+    ///
+    /// This is a lighter weight alternative to `entries` for when only the
+    /// names are needed, such as for listing the contents of an archive. The
+    /// contents of each entry are skipped over, and the names yielded take
+    /// long name extensions into account in the same way as `Entry::path_bytes`.
+    pub fn names(&mut self) -> io::Result<Names<'_, R>> {
+        let me: &mut Archive<dyn Read> = self;
+        me._entries(None).map(|fields| Names {
+            fields,
+            _ignored: marker::PhantomData,
+        })
+    }
+
     /// Unpacks the contents tarball into the specified `dst`.
     ///
     /// This function will iterate over the entire contents of this tarball,
@@ -106,7 +503,174 @@ impl<R: Read> Archive<R> {
     /// ```
     pub fn unpack<P: AsRef<Path>>(&mut self, dst: P) -> io::Result<()> {
         let me: &mut Archive<dyn Read> = self;
-        me._unpack(dst.as_ref())
+        me._unpack(dst.as_ref(), &mut |_| Ok(true))
+    }
+
+    /// Unpacks only those entries of this archive whose path is accepted by
+    /// `matcher` into `dst`.
+    ///
+    /// The matcher is given the full path of each entry, as returned by
+    /// `Entry::path_bytes`, so any matching scheme can be plugged in: exact
+    /// names, prefixes, or globs and regular expressions from other crates.
+    /// Otherwise this behaves exactly like `unpack`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use tar::Archive;
+    ///
+    /// let mut ar = Archive::new(File::open("foo.tar").unwrap());
+    /// ar.unpack_with("foo", |path| path.starts_with(b"docs/")).unwrap();
+    /// ```
+    pub fn unpack_with<P, F>(&mut self, dst: P, mut matcher: F) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&[u8]) -> bool,
+    {
+        let me: &mut Archive<dyn Read> = self;
+        me._unpack(dst.as_ref(), &mut |entry| Ok(matcher(&entry.path_bytes())))
+    }
+
+    /// Returns the first entry of this archive whose path is accepted by
+    /// `matcher`.
+    ///
+    /// The matcher is given the full path of each entry, as for
+    /// `unpack_with`. The contents of entries which don't match are skipped,
+    /// and `None` is returned if no entry matches.
+    pub fn find<F>(&mut self, mut matcher: F) -> io::Result<Option<Entry<'_, R>>>
+    where
+        F: FnMut(&[u8]) -> bool,
+    {
+        for entry in self.entries()? {
+            let entry = entry?;
+            if matcher(&entry.path_bytes()) {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns whether any entry of this archive has a path accepted by
+    /// `matcher`.
+    ///
+    /// See `find` for more information.
+    pub fn contains<F>(&mut self, matcher: F) -> io::Result<bool>
+    where
+        F: FnMut(&[u8]) -> bool,
+    {
+        self.find(matcher).map(|entry| entry.is_some())
+    }
+
+    /// Unpacks the contents of this archive into `dst` like `unpack`, checking
+    /// each entry with `Entry::validate` along the way.
+    ///
+    /// The returned list contains the path of every entry in the archive,
+    /// in order, along with whether its header validated. Entries which fail
+    /// validation are still extracted if `extract_invalid` is `true`, and
+    /// skipped otherwise. A header with a bad checksum is reported this way
+    /// too rather than aborting the whole call, as if
+    /// `set_lenient_checksums` were enabled. Every other unpacking option,
+    /// such as `set_extract_newer_than` and `set_atomic_unpack`, applies just
+    /// as it does for `unpack`, though entries it skips are still listed.
+    pub fn unpack_verified<P: AsRef<Path>>(
+        &mut self,
+        dst: P,
+        extract_invalid: bool,
+    ) -> io::Result<Vec<(PathBuf, bool)>> {
+        let me: &mut Archive<dyn Read> = self;
+        me._unpack_verified(dst.as_ref(), extract_invalid)
+    }
+
+    /// Copies every entry of this archive into `out`, giving `f` the chance to
+    /// modify each header on the way.
+    ///
+    /// Each entry's header is copied and passed to `f`, which can for example
+    /// zero out modification times or rewrite paths, and the entry is then
+    /// written to `out` with the original contents streamed through
+    /// unmodified. The size and checksum of the header are always recomputed
+    /// after `f` runs. If `f` leaves the path or link name alone, the full
+    /// name of the entry is used, so long names are carried over through GNU
+    /// extensions as necessary.
+    ///
+    /// Sparse entries are written out as regular files with their holes
+    /// filled in. Other information stored in pax extensions, such as
+    /// extended attributes, is not carried over.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use tar::{Archive, Builder};
+    ///
+    /// let mut ar = Archive::new(File::open("foo.tar").unwrap());
+    /// let mut out = Builder::new(File::create("normalized.tar").unwrap());
+    /// ar.rewrite(&mut out, |header| header.set_mtime(0)).unwrap();
+    /// out.finish().unwrap();
+    /// ```
+    pub fn rewrite<W, F>(&mut self, out: &mut Builder<W>, mut f: F) -> io::Result<()>
+    where
+        W: Write,
+        F: FnMut(&mut Header),
+    {
+        for entry in self.entries()? {
+            let mut entry = entry?;
+            let mut header = entry.header().clone();
+            let kind = header.entry_type();
+            let is_link = kind.is_hard_link() || kind.is_symlink();
+            if kind.is_gnu_sparse() {
+                header.set_entry_type(EntryType::Regular);
+            }
+            let path = header.path_bytes().into_owned();
+            let link_name = header.link_name_bytes().map(|l| l.into_owned());
+
+            f(&mut header);
+
+            let path = if *header.path_bytes() == *path {
+                entry.path()?.into_owned()
+            } else {
+                header.path()?.into_owned()
+            };
+            if is_link {
+                let target = match (header.link_name_bytes(), link_name) {
+                    (Some(ref new), Some(ref old)) if **new == **old => entry.link_name()?,
+                    _ => header.link_name()?,
+                };
+                let target = target
+                    .ok_or_else(|| other("link entry has no link name"))?
+                    .into_owned();
+                header.set_size(0);
+                out.append_link(&mut header, path, target)?;
+            } else {
+                header.set_size(entry.size());
+                out.append_data(&mut header, path, &mut entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the name and contents of every regular file in this archive.
+    ///
+    /// Names are returned as raw path bytes, in archive order. If
+    /// `include_other` is `true`, entries which aren't regular files (such as
+    /// directories and links) are included as well with empty contents;
+    /// otherwise they are skipped.
+    ///
+    /// Unlike `entries`, this loads the entire contents of the archive into
+    /// memory at once, so it is best suited to small archives, such as in
+    /// tests or simple tools.
+    pub fn read_all(&mut self, include_other: bool) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let me: &mut Archive<dyn Read> = self;
+        me._read_all(include_other)
+    }
+
+    /// Checks every entry of this archive with `Entry::validate`.
+    ///
+    /// The contents of each entry are skipped over rather than read. The first
+    /// inconsistent entry found is reported as an error.
+    pub fn verify(&mut self) -> io::Result<()> {
+        let me: &mut Archive<dyn Read> = self;
+        me._verify()
     }
 
     /// Indicate whether extended file attributes (xattrs on Unix) are preserved
@@ -124,7 +688,8 @@ impl<R: Read> Archive<R> {
     /// when unpacking this entry.
     ///
     /// This flag is disabled by default and is currently only implemented on
-    /// Unix.
+    /// Unix. In either case, the mode is masked to its permission bits before
+    /// being applied, so any file type bits stored in the mode are ignored.
     pub fn set_preserve_permissions(&mut self, preserve: bool) {
         self.inner.preserve_permissions = preserve;
     }
@@ -158,9 +723,152 @@ impl<R: Read> Archive<R> {
     pub fn set_ignore_zeros(&mut self, ignore_zeros: bool) {
         self.inner.ignore_zeros = ignore_zeros;
     }
+
+    /// Indicate whether entries with a bad header checksum are yielded
+    /// rather than stopping iteration with an error.
+    ///
+    /// This is meant for verification tools which want to list every entry
+    /// along with whether it is intact: the result of the check is available
+    /// from `Entry::checksum_valid`. Note that a header which fails its
+    /// checksum may well be garbage, so its other fields shouldn't be
+    /// trusted.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_lenient_checksums(&mut self, lenient: bool) {
+        self.inner.lenient_checksums = lenient;
+    }
+
+    /// Indicate whether entries with an empty path are rejected as malformed
+    /// while iterating.
+    ///
+    /// A header whose name field is entirely NUL (and which has no long name
+    /// or pax path describing it) otherwise parses with an empty path, which
+    /// is left to the caller to handle.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_reject_empty_paths(&mut self, reject: bool) {
+        self.inner.reject_empty_paths = reject;
+    }
+
+    /// Limits the number of entries which will be read from this archive.
+    ///
+    /// Once more than `max` entries have been found, iteration stops with an
+    /// error. This applies to every operation which walks the archive,
+    /// including `entries`, `names`, `verify` and `unpack`, and bounds the
+    /// work done on untrusted archives which claim an unreasonable number of
+    /// entries.
+    ///
+    /// By default there is no limit.
+    pub fn set_max_entries(&mut self, max: Option<u64>) {
+        self.inner.max_entries = max;
+    }
+
+    /// Limits the length, in bytes, of the paths and link names of entries
+    /// read from this archive.
+    ///
+    /// Reaching an entry whose full path or link name, after applying any
+    /// ustar prefix, GNU long name or pax record, is longer than `max` stops
+    /// iteration with an error. GNU long name and pax extension entries are
+    /// checked before their contents are read, so a crafted archive can't
+    /// make the reader buffer a name of unbounded size. A pax extension entry
+    /// may hold up to twice `max` bytes plus 64 KiB for its other records,
+    /// and its `path` and `linkpath` records are held to `max` as well.
+    ///
+    /// The default limit is 64 KiB.
+    pub fn set_max_path_len(&mut self, max: usize) {
+        self.inner.max_path_len = max;
+    }
+
+    /// Only extract entries modified after the given time, in seconds since
+    /// the Unix epoch.
+    ///
+    /// When set, `unpack` and `unpack_verified` skip every entry whose
+    /// modification time, as returned by `Entry::modified`, is not greater
+    /// than `mtime`, which is useful for
+    /// applying only the recent changes from a full backup. Unlike the
+    /// `overwrite` flag, this compares against an absolute time rather than
+    /// any file already on disk.
+    ///
+    /// By default all entries are extracted.
+    pub fn set_extract_newer_than(&mut self, mtime: Option<u64>) {
+        self.inner.extract_newer_than = mtime;
+    }
+
+    /// Indicate whether `unpack` should extract into a temporary directory
+    /// and only move it into place once everything has been extracted.
+    ///
+    /// When enabled, `unpack` requires that its destination doesn't exist
+    /// yet. Entries are extracted into a new directory next to it, which is
+    /// renamed to the destination on success and removed if anything fails,
+    /// so a failed extraction never leaves a half-populated tree behind.
+    /// Being a sibling, the temporary directory is normally on the same
+    /// filesystem and the rename is atomic. Should it be on another
+    /// filesystem anyway, the tree is copied into place instead, which is
+    /// not atomic; if that copy fails too, whatever was copied is removed
+    /// again. On Linux, macOS and Windows the final rename never replaces a
+    /// destination created in the meantime, even an empty directory, and an
+    /// `AlreadyExists` error is returned instead. Elsewhere, or on
+    /// filesystems which can't rename without replacing, the destination is
+    /// only checked once more right before the rename, which narrows the
+    /// window for such a race but doesn't close it. Any other failure to
+    /// rename is returned as an error as well.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_atomic_unpack(&mut self, atomic: bool) {
+        self.inner.atomic_unpack = atomic;
+    }
+
+    /// Provide a flag which, once set, aborts any further reading of this
+    /// archive.
+    ///
+    /// The flag is checked before every read from the underlying reader, so
+    /// iterating entries, reading their contents and unpacking all stop
+    /// between chunks with an error of kind `Other` once it is set. This
+    /// allows another thread to enforce a time limit on extracting a slow or
+    /// hostile stream. A read which is already blocked in the underlying
+    /// reader is not interrupted.
+    ///
+    /// By default no flag is checked.
+    pub fn set_cancel_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.inner.cancel = flag;
+    }
 }
 
 impl<R: Seek + Read> Archive<R> {
+    /// Indicate whether the reader should be seeked to the right position
+    /// before every read of an entry's contents.
+    ///
+    /// Entries from `entries_with_seek` normally trust that the reader is
+    /// still where the archive left it, so moving a shared reader while an
+    /// entry's contents are being read corrupts them. With this enabled, each
+    /// read first seeks to the position of the next byte of the contents,
+    /// at the cost of a seek per read.
+    ///
+    /// This flag is disabled by default, and has no effect on entries read
+    /// without seeking.
+    pub fn set_always_seek(&mut self, always_seek: bool) {
+        self.inner.always_seek = always_seek;
+    }
+
+    /// Create a new archive from a reader which may contain junk before the
+    /// first header, such as a shell wrapper or transport framing.
+    ///
+    /// The reader is scanned forward a byte at a time for the first 512 bytes
+    /// which form a header with a valid checksum, so the junk can be of any
+    /// length, and iteration of the returned archive will begin at that
+    /// header. The offset at which the archive was
+    /// found is reported by `start_offset`.
+    ///
+    /// This is opt-in as it can mask genuinely corrupt archives, which
+    /// `Archive::new` would instead report as an error. An error is returned
+    /// if no valid header is found before the end of the reader.
+    pub fn open_scanning(obj: R) -> io::Result<Archive<R>> {
+        let mut archive = Archive::new(obj);
+        let me: &mut Archive<dyn SeekRead> = &mut archive;
+        me._scan_for_start()?;
+        Ok(archive)
+    }
+
     /// Construct an iterator over the entries in this archive for a seekable
     /// reader. Seek will be used to efficiently skip over file contents.
     ///
@@ -176,6 +884,193 @@ impl<R: Seek + Read> Archive<R> {
             _ignored: marker::PhantomData,
         })
     }
+
+    /// Construct an iterator over the path names of the entries in this
+    /// archive for a seekable reader. Seek will be used to efficiently skip
+    /// over file contents.
+    pub fn names_with_seek(&mut self) -> io::Result<Names<'_, R>> {
+        let me: &Archive<dyn Read> = self;
+        let me_seekable: &Archive<dyn SeekRead> = self;
+        me._entries(Some(me_seekable)).map(|fields| Names {
+            fields,
+            _ignored: marker::PhantomData,
+        })
+    }
+
+    /// Returns the last entry of this archive, or `None` if it is empty.
+    ///
+    /// Tar archives can't be walked backwards, so this scans forward over all
+    /// headers, seeking past the contents of each entry, and then seeks back
+    /// to the headers of the final entry to return it positioned at the start
+    /// of its contents. This is useful when a manifest or index is appended
+    /// as the final member of an archive.
+    ///
+    /// Like `entries`, this can only be called while the archive is at its
+    /// starting position.
+    pub fn last(&mut self) -> io::Result<Option<Entry<'_, R>>> {
+        let me: &Archive<dyn Read> = self;
+        let me_seekable: &Archive<dyn SeekRead> = self;
+        let mut fields = me._entries(Some(me_seekable))?;
+        let mut last = None;
+        loop {
+            // Long name and pax extension headers are consumed along with
+            // the entry they describe, so this is where the entry starts.
+            let start = fields.next;
+            match fields.next() {
+                Some(entry) => {
+                    entry?;
+                    last = Some(start);
+                }
+                None => break,
+            }
+        }
+        let start = match last {
+            Some(start) => start,
+            None => return Ok(None),
+        };
+
+        (&me_seekable.inner).seek(SeekFrom::Start(start))?;
+        let mut fields = EntriesFields {
+            next: start,
+            done: false,
+            terminator: None,
+            count: 0,
+            ..fields
+        };
+        fields
+            .next()
+            .transpose()
+            .map(|entry| entry.map(|e| EntryFields::from(e).into_entry()))
+    }
+
+    /// Converts this archive into an iterator over its entries which owns the
+    /// archive.
+    ///
+    /// Unlike `entries`, neither the returned iterator nor the entries it
+    /// yields borrow the archive, so they can be stored or returned from
+    /// functions. See `IntoEntries` for how the reader is shared between
+    /// them.
+    pub fn into_entries(self) -> io::Result<IntoEntries<R>> {
+        let next = {
+            let me: &Archive<dyn Read> = &self;
+            me._entries(None)?.next
+        };
+        Ok(IntoEntries {
+            archive: Rc::new(self),
+            next,
+            done: false,
+            terminator: None,
+            count: 0,
+        })
+    }
+
+    /// Returns the records of the global pax extensions header at the start
+    /// of this archive, if there is one.
+    ///
+    /// Global headers carry archive-wide defaults, such as a `comment`, which
+    /// are otherwise skipped over when iterating entries. Each record is
+    /// returned as a raw key/value pair, and an empty list is returned if the
+    /// archive doesn't begin with a global header. The archive is rewound
+    /// afterwards so it can still be read from the start.
+    pub fn global_pax(&mut self) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let me: &Archive<dyn Read> = self;
+        let me_seekable: &Archive<dyn SeekRead> = self;
+        let records = (|| {
+            let mut fields = me._entries(Some(me_seekable))?;
+            fields.raw = true;
+            let mut records = Vec::new();
+            let mut entry = match fields.next() {
+                Some(entry) => entry?,
+                None => return Ok(records),
+            };
+            if !entry.header().entry_type().is_pax_global_extensions() {
+                return Ok(records);
+            }
+            if let Some(extensions) = entry.pax_extensions()? {
+                for extension in extensions {
+                    let extension = extension?;
+                    records.push((
+                        extension.key_bytes().to_vec(),
+                        extension.value_bytes().to_vec(),
+                    ));
+                }
+            }
+            Ok(records)
+        })();
+        (&me_seekable.inner).seek(SeekFrom::Start(me.inner.start))?;
+        records
+    }
+
+    /// Returns the `comment` record of the global pax extensions header at
+    /// the start of this archive, if there is one.
+    ///
+    /// Archivers use this to label an archive, for example with the details
+    /// of the build which produced it. `None` is returned if there is no
+    /// global header or it has no comment, and an error if the comment isn't
+    /// valid UTF-8. See `global_pax` for more information.
+    pub fn comment(&mut self) -> io::Result<Option<String>> {
+        let records = self.global_pax()?;
+        let comment = records.into_iter().find(|(key, _)| key == b"comment");
+        match comment {
+            Some((_, value)) => String::from_utf8(value)
+                .map(Some)
+                .map_err(|_| other("pax comment is not valid UTF-8")),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the path of each entry in this archive along with up to the
+    /// first `n` bytes of its contents.
+    ///
+    /// This is meant for classifying the entries of an archive, for example
+    /// by looking for magic numbers, without reading all of their contents:
+    /// only the first `n` bytes of each entry are read, and the archive is
+    /// seeked past the rest.
+    pub fn sniff(&mut self, n: usize) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut sniffed = Vec::new();
+        for entry in self.entries_with_seek()? {
+            let entry = entry?;
+            let name = entry.path_bytes().into_owned();
+            let mut head = Vec::new();
+            entry.take(n as u64).read_to_end(&mut head)?;
+            sniffed.push((name, head));
+        }
+        Ok(sniffed)
+    }
+
+    /// Construct an iterator over only those entries of this archive for
+    /// which `want` returns `true`.
+    ///
+    /// The predicate is called with each entry as soon as its header has been
+    /// parsed. The contents of entries which aren't wanted are never read;
+    /// the archive is instead seeked directly to the next header. This
+    /// minimizes I/O when looking for a few entries in a large archive.
+    pub fn scan<F>(&mut self, want: F) -> io::Result<Scan<'_, R, F>>
+    where
+        F: FnMut(&Entry<'_, R>) -> bool,
+    {
+        self.entries_with_seek()
+            .map(|entries| Scan { entries, want })
+    }
+
+    /// Returns the offset of the first zero block terminating this archive.
+    ///
+    /// This scans over every header in the archive, seeking past file
+    /// contents, and reports the position of the zero block which marks the
+    /// end of the archive. This is the position at which new entries should
+    /// be written when appending to an existing archive.
+    ///
+    /// `Ok(None)` is returned if the archive ends without a terminator, or if
+    /// zeroed headers are being ignored via `set_ignore_zeros`.
+    pub fn terminator_offset(&mut self) -> io::Result<Option<u64>> {
+        let me: &Archive<dyn Read> = self;
+        let me_seekable: &Archive<dyn SeekRead> = self;
+        let mut fields = me._entries(Some(me_seekable))?;
+        for entry in &mut fields {
+            entry?;
+        }
+        Ok(fields.terminator)
+    }
 }
 
 impl Archive<dyn Read + '_> {
@@ -183,22 +1078,162 @@ impl Archive<dyn Read + '_> {
         &'a self,
         seekable_archive: Option<&'a Archive<dyn SeekRead + 'a>>,
     ) -> io::Result<EntriesFields<'a>> {
-        if self.inner.pos.get() != 0 {
+        if self.inner.pos.get() != self.inner.start {
             return Err(other(
                 "cannot call entries unless archive is at \
-                 position 0",
+                 its starting position",
             ));
         }
         Ok(EntriesFields {
             archive: self,
             seekable_archive,
             done: false,
-            next: 0,
+            next: self.inner.start,
             raw: false,
+            terminator: None,
+            count: 0,
         })
     }
 
-    fn _unpack(&mut self, dst: &Path) -> io::Result<()> {
+    fn _unpack_verified(
+        &mut self,
+        dst: &Path,
+        extract_invalid: bool,
+    ) -> io::Result<Vec<(PathBuf, bool)>> {
+        let lenient_checksums = self.inner.lenient_checksums;
+        self.inner.lenient_checksums = true;
+        let mut results = Vec::new();
+        let unpacked = self._unpack(dst, &mut |file| {
+            let valid = file.checksum_valid() && file.validate().is_ok();
+            results.push((file.path()?.into_owned(), valid));
+            Ok(valid || extract_invalid)
+        });
+        self.inner.lenient_checksums = lenient_checksums;
+        unpacked.map(|()| results)
+    }
+
+    fn _status(&mut self) -> io::Result<ArchiveStatus> {
+        let ignore_zeros = self.inner.ignore_zeros;
+        self.inner.ignore_zeros = false;
+        let status = (|| {
+            let mut fields = self._entries(None)?;
+            let mut found = false;
+            for entry in &mut fields {
+                match entry {
+                    Ok(_) => found = true,
+                    Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        return Ok(ArchiveStatus::Truncated);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(match fields.terminator {
+                None => ArchiveStatus::Truncated,
+                Some(_) if found => ArchiveStatus::Complete,
+                Some(_) => ArchiveStatus::Empty,
+            })
+        })();
+        self.inner.ignore_zeros = ignore_zeros;
+        status
+    }
+
+    fn _features_used(&mut self) -> io::Result<FeatureSet> {
+        let mut features = FeatureSet::default();
+        for entry in self._entries(None)? {
+            let mut fields = EntryFields::from(entry?);
+            let kind = fields.header.entry_type();
+            features.gnu_long_names |= fields.gnu_long_names;
+            features.sparse |= kind.is_gnu_sparse();
+            features.base256 |= uses_base256(&fields.header);
+            features.device_nodes |= kind.is_character_special() || kind.is_block_special();
+            features.multi_volume |= kind.as_byte() == b'M' || kind.as_byte() == b'V';
+            if let Some(pax) = fields.pax_extensions()? {
+                features.pax = true;
+                for extension in pax {
+                    if extension?.key_bytes().starts_with(b"GNU.sparse.") {
+                        features.sparse = true;
+                    }
+                }
+            }
+        }
+        Ok(features)
+    }
+
+    fn is_newer<R: Read>(&self, entry: &Entry<'_, R>) -> io::Result<bool> {
+        match self.inner.extract_newer_than {
+            Some(mtime) => Ok(entry.modified()? > UNIX_EPOCH + Duration::from_secs(mtime)),
+            None => Ok(true),
+        }
+    }
+
+    fn _read_all(&mut self, include_other: bool) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut files = Vec::new();
+        for entry in self._entries(None)? {
+            let entry = entry?;
+            let name = entry.path_bytes().into_owned();
+            let kind = entry.header().entry_type();
+            if kind.is_file() || kind.is_contiguous() || kind.is_gnu_sparse() {
+                let data = EntryFields::from(entry).read_all()?;
+                files.push((name, data));
+            } else if include_other {
+                files.push((name, Vec::new()));
+            }
+        }
+        Ok(files)
+    }
+
+    fn _verify(&mut self) -> io::Result<()> {
+        for entry in self._entries(None)? {
+            entry?.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Unpacks the entries accepted by `filter` into `dst`, honoring all of
+    /// the unpacking options of this archive.
+    fn _unpack(&mut self, dst: &Path, filter: &mut UnpackFilter<'_>) -> io::Result<()> {
+        if !self.inner.atomic_unpack {
+            return self._unpack_in(dst, filter);
+        }
+        if dst.symlink_metadata().is_ok() {
+            return Err(TarError::new(
+                format!("failed to unpack into `{}`", dst.display()),
+                io::Error::new(io::ErrorKind::AlreadyExists, "destination already exists"),
+            )
+            .into());
+        }
+        let tmp = create_sibling_dir(dst).map_err(|e| {
+            TarError::new(
+                format!("failed to create a directory next to `{}`", dst.display()),
+                e,
+            )
+        })?;
+        let result = self._unpack_in(&tmp, filter).and_then(|()| {
+            let moved = rename_no_replace(&tmp, dst).or_else(|e| {
+                if !is_cross_device(&e) {
+                    return Err(e);
+                }
+                // Renaming can't move the directory to another filesystem, so
+                // copy it instead. Only a destination created here is removed
+                // again on failure.
+                fs::create_dir(dst)?;
+                let copied = copy_dir_contents(&tmp, dst);
+                if copied.is_err() {
+                    let _ = fs::remove_dir_all(dst);
+                }
+                copied
+            });
+            moved.map_err(|e| {
+                TarError::new(format!("failed to move `{}` into place", dst.display()), e).into()
+            })
+        });
+        if tmp.symlink_metadata().is_ok() {
+            let _ = fs::remove_dir_all(&tmp);
+        }
+        result
+    }
+
+    fn _unpack_in(&mut self, dst: &Path, filter: &mut UnpackFilter<'_>) -> io::Result<()> {
         if dst.symlink_metadata().is_err() {
             fs::create_dir_all(&dst)
                 .map_err(|e| TarError::new(format!("failed to create `{}`", dst.display()), e))?;
@@ -217,18 +1252,92 @@ impl Archive<dyn Read + '_> {
         let mut directories = Vec::new();
         for entry in self._entries(None)? {
             let mut file = entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+            if !filter(&file)? || !self.is_newer(&file)? {
+                continue;
+            }
             if file.header().entry_type() == crate::EntryType::Directory {
                 directories.push(file);
             } else {
                 file.unpack_in(dst)?;
             }
         }
-        for mut dir in directories {
-            dir.unpack_in(dst)?;
-        }
+        for mut dir in directories {
+            dir.unpack_in(dst)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'x> Archive<io::Cursor<&'x [u8]>> {
+    /// Construct an iterator over the entries of this in-memory archive which
+    /// also yields the contents of each entry as a slice of the archive,
+    /// without copying them.
+    ///
+    /// The slice is `None` for sparse entries, whose contents aren't stored
+    /// contiguously. It always holds exactly the bytes reading the entry
+    /// would produce, so for example it's empty for hard links even if their
+    /// header lists a size. Contents which aren't read are seeked over, as
+    /// with `entries_with_seek`.
+    pub fn entries_with_slices(&mut self) -> io::Result<SliceEntries<'_, 'x>> {
+        let (data, position) = {
+            let cursor = self.inner.obj.borrow();
+            (*cursor.get_ref(), cursor.position())
+        };
+        let base = match self.inner.base.get() {
+            Some(base) => base,
+            None => position
+                .checked_sub(self.inner.pos.get())
+                .ok_or_else(|| other("seek position out of bounds"))?,
+        };
+        Ok(SliceEntries {
+            entries: self.entries_with_seek()?,
+            data,
+            base,
+        })
+    }
+}
+
+impl Archive<dyn SeekRead + '_> {
+    fn _scan_for_start(&mut self) -> io::Result<()> {
+        let mut offset = self.inner.pos.get();
+        let mut window = Vec::new();
+        let mut chunk = [0; 8 * BLOCK_SIZE];
+        loop {
+            // Try every offset with a whole block after it, keeping the
+            // remaining bytes for when the next chunk has been read.
+            let mut i = 0;
+            while i + BLOCK_SIZE <= window.len() {
+                if is_header_block(&window[i..i + BLOCK_SIZE]) {
+                    let start = offset + i as u64;
+                    self.inner.start = (&self.inner).seek(SeekFrom::Start(start))?;
+                    return Ok(());
+                }
+                i += 1;
+            }
+            window.drain(..i);
+            offset += i as u64;
+            let n = match (&self.inner).read(&mut chunk) {
+                Ok(0) => return Err(other("no valid archive header found")),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            window.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
 
-        Ok(())
+/// Returns whether `block` is a non-zero header with a valid checksum.
+fn is_header_block(block: &[u8]) -> bool {
+    let header = Header::from_byte_slice(block);
+    // Most junk is ruled out by the checksum field alone, before the more
+    // expensive sum over the whole block.
+    let cksum = &header.as_old().cksum;
+    if !cksum.iter().all(|b| matches!(b, b'0'..=b'7' | b' ' | 0)) {
+        return false;
     }
+    !block.iter().all(|b| *b == 0) && header.cksum().ok() == Some(header_sum(header))
 }
 
 impl<'a, R: Read> Entries<'a, R> {
@@ -246,7 +1355,66 @@ impl<'a, R: Read> Entries<'a, R> {
             _ignored: marker::PhantomData,
         }
     }
+
+    /// Advances to the next entry, keeping this iterator borrowed for as
+    /// long as the returned entry is alive.
+    ///
+    /// Entries returned by the `Iterator` implementation may be kept while
+    /// the iterator advances, but their contents can then no longer be read
+    /// correctly, as the archive has moved past them. The entry returned
+    /// here holds a mutable borrow of the iterator instead, so the compiler
+    /// ensures it has been dropped before the next entry is requested.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io;
+    /// use tar::Archive;
+    ///
+    /// let mut ar = Archive::new(File::open("foo.tar").unwrap());
+    /// let mut entries = ar.entries().unwrap();
+    /// while let Some(entry) = entries.next_borrowed() {
+    ///     let mut entry = entry.unwrap();
+    ///     println!("{}", entry.path().unwrap().display());
+    ///     io::copy(&mut *entry, &mut io::stdout()).unwrap();
+    ///     // `entry` is dropped here, which releases `entries` so that the
+    ///     // loop can advance it.
+    /// }
+    /// ```
+    pub fn next_borrowed(&mut self) -> Option<io::Result<BorrowedEntry<'_, 'a, R>>> {
+        self.next().map(|result| {
+            result.map(|entry| BorrowedEntry {
+                entry,
+                _entries: marker::PhantomData,
+            })
+        })
+    }
+
+    /// Returns a reader over the contents of the following entries for which
+    /// `group` returns `true`, as a single continuous stream.
+    ///
+    /// This is meant for archives which use a custom convention of splitting
+    /// one logical file across several consecutive entries, such as
+    /// `big.part0`, `big.part1` and so on; it has nothing to do with the
+    /// multi-volume archives of GNU tar. Entries are read as the stream
+    /// reaches them, and the group ends at the first entry for which `group`
+    /// returns `false`. That entry is kept rather than lost, and can be
+    /// retrieved with `Concat::into_next`.
+    pub fn concat<F>(&mut self, group: F) -> Concat<'_, 'a, R, F>
+    where
+        F: FnMut(&Entry<'a, R>) -> bool,
+    {
+        Concat {
+            entries: self,
+            group,
+            current: None,
+            next: None,
+            done: false,
+        }
+    }
 }
+
 impl<'a, R: Read> Iterator for Entries<'a, R> {
     type Item = io::Result<Entry<'a, R>>;
 
@@ -257,6 +1425,66 @@ impl<'a, R: Read> Iterator for Entries<'a, R> {
     }
 }
 
+impl<'a, R: Read, F> Iterator for Scan<'a, R, F>
+where
+    F: FnMut(&Entry<'a, R>) -> bool,
+{
+    type Item = io::Result<Entry<'a, R>>;
+
+    fn next(&mut self) -> Option<io::Result<Entry<'a, R>>> {
+        loop {
+            match self.entries.next()? {
+                Ok(entry) => {
+                    if (self.want)(&entry) {
+                        return Some(Ok(entry));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl<'a, R: Read> TryEntries<'a, R> {
+    /// Returns the error which stopped iteration, if any.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Takes the error which stopped iteration, if any, leaving `None` in
+    /// its place.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+impl<'a, R: Read> Iterator for TryEntries<'a, R> {
+    type Item = Entry<'a, R>;
+
+    fn next(&mut self) -> Option<Entry<'a, R>> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.entries.next()? {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+}
+
+impl<'a, R: Read> Iterator for Names<'a, R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        self.fields
+            .next()
+            .map(|result| result.map(|e| e.path_bytes().into_owned()))
+    }
+}
+
 #[allow(unused_assignments)] // https://github.com/rust-lang/rust/issues/22630
 impl<'a> EntriesFields<'a> {
     fn next_entry_raw(
@@ -267,8 +1495,7 @@ impl<'a> EntriesFields<'a> {
         let mut header_pos = self.next;
         loop {
             // Seek to the start of the next header in the archive
-            let delta = self.next - self.archive.inner.pos.get();
-            self.skip(delta)?;
+            self.skip_to(self.next)?;
 
             // EOF is an indicator that we are at the end of the archive.
             if !try_read_all(&mut &self.archive.inner, header.as_mut_bytes())? {
@@ -284,6 +1511,7 @@ impl<'a> EntriesFields<'a> {
             }
 
             if !self.archive.inner.ignore_zeros {
+                self.terminator = Some(header_pos);
                 return Ok(None);
             }
             self.next += BLOCK_SIZE as u64;
@@ -291,13 +1519,13 @@ impl<'a> EntriesFields<'a> {
         }
 
         // Make sure the checksum is ok
-        let sum = header.as_bytes()[..148]
-            .iter()
-            .chain(&header.as_bytes()[156..])
-            .fold(0, |a, b| a + (*b as u32))
-            + 8 * 32;
-        let cksum = header.cksum()?;
-        if sum != cksum {
+        let lenient = self.archive.inner.lenient_checksums;
+        let checksum_ok = match header.cksum() {
+            Ok(cksum) => cksum == header_sum(&header),
+            Err(_) if lenient => false,
+            Err(e) => return Err(e),
+        };
+        if !checksum_ok && !lenient {
             return Err(other("archive header checksum mismatch"));
         }
 
@@ -308,14 +1536,25 @@ impl<'a> EntriesFields<'a> {
                 size = pax_size;
             }
         }
+        // Some archivers record the size of the target in the size field of
+        // hard links, but they never carry a body of their own. Only the
+        // declared size is kept so it can still be reported.
+        let body_size = if header.entry_type().is_hard_link() {
+            0
+        } else {
+            size
+        };
         let ret = EntryFields {
             size: size,
             header_pos: header_pos,
             file_pos: file_pos,
-            data: vec![EntryIo::Data((&self.archive.inner).take(size))],
+            data: vec![EntryIo::Data((&self.archive.inner).take(body_size))],
+            archive: &self.archive.inner,
             header: header,
+            checksum_ok,
             long_pathname: None,
             long_linkname: None,
+            gnu_long_names: false,
             pax_extensions: None,
             unpack_xattrs: self.archive.inner.unpack_xattrs,
             preserve_permissions: self.archive.inner.preserve_permissions,
@@ -326,7 +1565,7 @@ impl<'a> EntriesFields<'a> {
 
         // Store where the next entry is, rounding up by 512 bytes (the size of
         // a header);
-        let size = size
+        let size = body_size
             .checked_add(BLOCK_SIZE as u64 - 1)
             .ok_or_else(|| other("size overflow"))?;
         self.next = self
@@ -337,6 +1576,52 @@ impl<'a> EntriesFields<'a> {
         Ok(Some(ret.into_entry()))
     }
 
+    fn check_long_name_size(&self, entry: &Entry<'a, io::Empty>) -> io::Result<()> {
+        // The name may be followed by a terminating nul.
+        let max = self.archive.inner.max_path_len as u64;
+        if entry.size() > max + 1 {
+            return Err(other(&format!(
+                "long name entry of {} bytes exceeds the maximum path \
+                 length of {} bytes",
+                entry.size(),
+                max
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_pax_extensions_size(&self, entry: &Entry<'a, io::Empty>) -> io::Result<()> {
+        // Leave room for both a path and a link name, plus the other records.
+        let max = self.archive.inner.max_path_len as u64;
+        let limit = max.saturating_mul(2).saturating_add(PAX_EXTENSIONS_SLACK);
+        if entry.size() > limit {
+            return Err(other(&format!(
+                "pax extensions entry of {} bytes exceeds the maximum \
+                 size of {} bytes",
+                entry.size(),
+                limit
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_pax_path_len(&self, data: &[u8]) -> io::Result<()> {
+        let max = self.archive.inner.max_path_len;
+        for extension in PaxExtensions::new(data).filter_map(|e| e.ok()) {
+            let key = extension.key_bytes();
+            if (key == b"path" || key == b"linkpath") && extension.value_bytes().len() > max {
+                return Err(other(&format!(
+                    "pax {} record of {} bytes exceeds the maximum path \
+                     length of {} bytes",
+                    String::from_utf8_lossy(key),
+                    extension.value_bytes().len(),
+                    max
+                )));
+            }
+        }
+        Ok(())
+    }
+
     fn next_entry(&mut self) -> io::Result<Option<Entry<'a, io::Empty>>> {
         if self.raw {
             return self.next_entry_raw(None);
@@ -346,6 +1631,7 @@ impl<'a> EntriesFields<'a> {
         let mut gnu_longlink = None;
         let mut pax_extensions = None;
         let mut pax_size = None;
+        let mut checksum_ok = true;
         let mut processed = 0;
         loop {
             processed += 1;
@@ -359,6 +1645,7 @@ impl<'a> EntriesFields<'a> {
                 }
                 None => return Ok(None),
             };
+            checksum_ok &= entry.checksum_valid();
 
             let is_recognized_header =
                 entry.header().as_gnu().is_some() || entry.header().as_ustar().is_some();
@@ -370,6 +1657,7 @@ impl<'a> EntriesFields<'a> {
                          the same member",
                     ));
                 }
+                self.check_long_name_size(&entry)?;
                 gnu_longname = Some(EntryFields::from(entry).read_all()?);
                 continue;
             }
@@ -381,6 +1669,7 @@ impl<'a> EntriesFields<'a> {
                          the same member",
                     ));
                 }
+                self.check_long_name_size(&entry)?;
                 gnu_longlink = Some(EntryFields::from(entry).read_all()?);
                 continue;
             }
@@ -392,10 +1681,11 @@ impl<'a> EntriesFields<'a> {
                          the same member",
                     ));
                 }
-                pax_extensions = Some(EntryFields::from(entry).read_all()?);
-                if let Some(pax_extensions_ref) = &pax_extensions {
-                    pax_size = pax_extensions_size(pax_extensions_ref);
-                }
+                self.check_pax_extensions_size(&entry)?;
+                let data = EntryFields::from(entry).read_all()?;
+                self.check_pax_path_len(&data)?;
+                pax_size = pax_extensions_size(&data);
+                pax_extensions = Some(data);
                 // Not an entry
                 // Keep pax_extensions for the next ustar header
                 processed -= 1;
@@ -405,32 +1695,75 @@ impl<'a> EntriesFields<'a> {
             let mut fields = EntryFields::from(entry);
             fields.pax_extensions = pax_extensions;
             pax_extensions = None; // Reset pax_extensions after use
-            if is_recognized_header && fields.is_pax_sparse() {
-                gnu_longname = fields.pax_sparse_name();
+            fields.gnu_long_names = gnu_longname.is_some() || gnu_longlink.is_some();
+            if is_recognized_header {
+                if let Some(name) = fields.pax_sparse_name() {
+                    gnu_longname = Some(name);
+                }
             }
             fields.long_pathname = gnu_longname;
             fields.long_linkname = gnu_longlink;
+            fields.checksum_ok = checksum_ok;
             self.parse_sparse_header(&mut fields)?;
-            return Ok(Some(fields.into_entry()));
+            let entry = fields.into_entry();
+            if self.archive.inner.reject_empty_paths && entry.path_bytes().is_empty() {
+                return Err(other("archive entry has an empty path"));
+            }
+            let max = self.archive.inner.max_path_len;
+            let link_len = entry.link_name_bytes().map_or(0, |l| l.len());
+            if entry.path_bytes().len() > max || link_len > max {
+                return Err(other(&format!(
+                    "archive entry has a path or link name longer than the \
+                     maximum of {} bytes",
+                    max
+                )));
+            }
+            return Ok(Some(entry));
         }
     }
 
     fn parse_sparse_header(&mut self, entry: &mut EntryFields<'a>) -> io::Result<()> {
-        if !entry.is_pax_sparse() && !entry.header.entry_type().is_gnu_sparse() {
+        let pax_sparse_map = entry.pax_sparse_map()?;
+        if !entry.is_pax_sparse()
+            && pax_sparse_map.is_none()
+            && !entry.header.entry_type().is_gnu_sparse()
+        {
             return Ok(());
         }
         let mut sparse_map = Vec::<SparseEntry>::new();
         let mut real_size = 0;
-        if entry.is_pax_sparse() {
+        if let Some((size, map)) = pax_sparse_map {
+            // Versions 0.0 and 0.1 of the PAX sparse format describe the map
+            // entirely in the extended header, so the data is just the blocks.
+            real_size = size;
+            sparse_map = map;
+        } else if entry.is_pax_sparse() {
             real_size = entry.pax_sparse_realsize()?;
-            let mut num_bytes_read = 0;
-            let mut reader = io::BufReader::with_capacity(BLOCK_SIZE, &self.archive.inner);
+            // The map is stored as decimal lines at the start of the data,
+            // padded out to a whole number of blocks. Read it a block at a
+            // time so the reader is left at the start of the file data even
+            // if the underlying reader returns short reads.
+            let mut map = Vec::new();
+            let mut pos = 0;
+            let mut reader = &self.archive.inner;
+            let size = entry.size;
             let mut read_decimal_line = || -> io::Result<u64> {
-                let mut str = String::new();
-                num_bytes_read += reader.read_line(&mut str)?;
-                str.strip_suffix("\n")
-                    .and_then(|s| s.parse::<u64>().ok())
-                    .ok_or_else(|| other("failed to read a decimal line"))
+                loop {
+                    if let Some(i) = map[pos..].iter().position(|b| *b == b'\n') {
+                        let line = &map[pos..pos + i];
+                        pos += i + 1;
+                        return std::str::from_utf8(line)
+                            .ok()
+                            .and_then(|s| s.parse::<u64>().ok())
+                            .ok_or_else(|| other("failed to read a decimal line"));
+                    }
+                    let len = map.len();
+                    if len as u64 + BLOCK_SIZE as u64 > size {
+                        return Err(other("sparse map extends past the end of the entry"));
+                    }
+                    map.resize(len + BLOCK_SIZE, 0);
+                    reader.read_exact(&mut map[len..])?;
+                }
             };
 
             let num_entries = read_decimal_line()?;
@@ -439,8 +1772,7 @@ impl<'a> EntriesFields<'a> {
                 let size = read_decimal_line()?;
                 sparse_map.push(SparseEntry { offset, size });
             }
-            let rem = BLOCK_SIZE - (num_bytes_read % BLOCK_SIZE);
-            entry.size -= (num_bytes_read + rem) as u64;
+            entry.size -= map.len() as u64;
         } else if entry.header.entry_type().is_gnu_sparse() {
             let gnu = match entry.header.as_gnu() {
                 Some(gnu) => gnu,
@@ -551,19 +1883,22 @@ impl<'a> EntriesFields<'a> {
         Ok(())
     }
 
-    fn skip(&mut self, mut amt: u64) -> io::Result<()> {
+    fn skip_to(&mut self, next: u64) -> io::Result<()> {
         if let Some(seekable_archive) = self.seekable_archive {
-            let pos = io::SeekFrom::Current(
-                i64::try_from(amt).map_err(|_| other("seek position out of bounds"))?,
-            );
-            (&seekable_archive.inner).seek(pos)?;
+            // Seek to the absolute offset rather than relative to our tracked
+            // position, which is stale if the reader was moved by someone else.
+            (&seekable_archive.inner).seek(SeekFrom::Start(next))?;
         } else {
+            let mut amt = next - self.archive.inner.pos.get();
             let mut buf = [0u8; 4096 * 8];
             while amt > 0 {
                 let n = cmp::min(amt, buf.len() as u64);
                 let n = (&self.archive.inner).read(&mut buf[..n as usize])?;
                 if n == 0 {
-                    return Err(other("unexpected EOF during skip"));
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "unexpected EOF during skip",
+                    ));
                 }
                 amt -= n as u64;
             }
@@ -572,6 +1907,50 @@ impl<'a> EntriesFields<'a> {
     }
 }
 
+impl<'a, 'x> Iterator for SliceEntries<'a, 'x> {
+    type Item = io::Result<(Entry<'a, io::Cursor<&'x [u8]>>, Option<&'x [u8]>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let fields = match self.entries.next()? {
+            Ok(entry) => EntryFields::from(entry),
+            Err(e) => return Some(Err(e)),
+        };
+        let body = fields.contiguous_len().and_then(|len| {
+            let start = usize::try_from(self.base.checked_add(fields.file_pos)?).ok()?;
+            let end = start.checked_add(usize::try_from(len).ok()?)?;
+            self.data.get(start..end)
+        });
+        Some(Ok((fields.into_entry(), body)))
+    }
+}
+
+impl<'a> EntriesFields<'a> {
+    /// Makes every read of the contents of `entry` seek first, if requested
+    /// with `set_always_seek`.
+    fn seek_before_reads(&self, entry: Entry<'a, io::Empty>) -> Entry<'a, io::Empty> {
+        let seekable_archive = match self.seekable_archive {
+            Some(archive) if self.archive.inner.always_seek => archive,
+            _ => return entry,
+        };
+        let mut fields = EntryFields::from(entry);
+        // The entry's headers have all been read, so its contents start at
+        // the current position.
+        let mut pos = self.archive.inner.pos.get();
+        for io in fields.data.iter_mut() {
+            if let EntryIo::Data(data) = io {
+                let len = data.limit();
+                *io = EntryIo::Seek(SeekData {
+                    archive: &seekable_archive.inner,
+                    pos,
+                    left: len,
+                });
+                pos += len;
+            }
+        }
+        fields.into_entry()
+    }
+}
+
 impl<'a> Iterator for EntriesFields<'a> {
     type Item = io::Result<Entry<'a, io::Empty>>;
 
@@ -580,7 +1959,20 @@ impl<'a> Iterator for EntriesFields<'a> {
             None
         } else {
             match self.next_entry() {
-                Ok(Some(e)) => Some(Ok(e)),
+                Ok(Some(e)) => {
+                    let e = self.seek_before_reads(e);
+                    self.count += 1;
+                    match self.archive.inner.max_entries {
+                        Some(max) if self.count > max => {
+                            self.done = true;
+                            Some(Err(other(&format!(
+                                "archive contains more than the maximum of {} entries",
+                                max
+                            ))))
+                        }
+                        _ => Some(Ok(e)),
+                    }
+                }
                 Ok(None) => {
                     self.done = true;
                     None
@@ -594,22 +1986,563 @@ impl<'a> Iterator for EntriesFields<'a> {
     }
 }
 
+impl<R: Read + Seek> Iterator for IntoEntries<R> {
+    type Item = io::Result<OwnedEntry<R>>;
+
+    fn next(&mut self) -> Option<io::Result<OwnedEntry<R>>> {
+        if self.done {
+            return None;
+        }
+        let archive = Rc::clone(&self.archive);
+        let me: &Archive<dyn Read> = &*archive;
+        let me_seekable: &Archive<dyn SeekRead> = &*archive;
+
+        // Entries yielded earlier may have moved the reader since.
+        if let Err(e) = (&me_seekable.inner).seek(SeekFrom::Start(self.next)) {
+            self.done = true;
+            return Some(Err(e));
+        }
+        let mut fields = EntriesFields {
+            archive: me,
+            seekable_archive: Some(me_seekable),
+            next: self.next,
+            done: false,
+            raw: false,
+            terminator: self.terminator,
+            count: self.count,
+        };
+        let entry = fields
+            .next()
+            .map(|entry| entry.map(|entry| OwnedEntry::new(&archive, entry)));
+        self.next = fields.next;
+        self.done = fields.done;
+        self.terminator = fields.terminator;
+        self.count = fields.count;
+        entry
+    }
+}
+
+impl<R: Read + Seek> OwnedEntry<R> {
+    fn new(archive: &Rc<Archive<R>>, entry: Entry<'_, io::Empty>) -> OwnedEntry<R> {
+        let path = entry.path_bytes().into_owned();
+        let link_name = entry.link_name_bytes().map(|l| l.into_owned());
+        let fields = EntryFields::from(entry);
+
+        // The reader has just finished parsing this entry's headers, so its
+        // contents start right here.
+        let mut pos = archive.inner.pos.get();
+        let segments = fields
+            .data
+            .iter()
+            .map(|io| match io {
+                EntryIo::Pad(io) => Segment::Pad(io.limit()),
+                EntryIo::Data(io) => {
+                    let segment = Segment::Data {
+                        pos,
+                        len: io.limit(),
+                    };
+                    pos += io.limit();
+                    segment
+                }
+                EntryIo::Seek(io) => Segment::Data {
+                    pos: io.pos(),
+                    len: io.limit(),
+                },
+            })
+            .collect();
+        OwnedEntry {
+            archive: Rc::clone(archive),
+            header: fields.header,
+            path,
+            link_name,
+            size: fields.size,
+            segments,
+            segment: 0,
+        }
+    }
+
+    /// Returns the header of this entry.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the path name for this entry.
+    ///
+    /// See `Entry::path` for more information.
+    pub fn path(&self) -> io::Result<Cow<'_, Path>> {
+        bytes2path(Cow::Borrowed(&self.path))
+    }
+
+    /// Returns the raw bytes listed for this entry.
+    ///
+    /// See `Entry::path_bytes` for more information.
+    pub fn path_bytes(&self) -> &[u8] {
+        &self.path
+    }
+
+    /// Returns the link name for this entry, if any is found.
+    ///
+    /// See `Entry::link_name` for more information.
+    pub fn link_name(&self) -> io::Result<Option<Cow<'_, Path>>> {
+        match self.link_name {
+            Some(ref bytes) => bytes2path(Cow::Borrowed(bytes)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the link name for this entry, in bytes, if listed.
+    ///
+    /// See `Entry::link_name_bytes` for more information.
+    pub fn link_name_bytes(&self) -> Option<&[u8]> {
+        self.link_name.as_deref()
+    }
+
+    /// Returns the size of this entry.
+    ///
+    /// See `Entry::size` for more information.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl<R: Read + Seek> Read for OwnedEntry<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let me_seekable: &Archive<dyn SeekRead> = &*self.archive;
+        loop {
+            match self.segments.get_mut(self.segment) {
+                Some(Segment::Pad(len)) if *len > 0 => {
+                    let n = cmp::min(*len, into.len() as u64) as usize;
+                    into[..n].iter_mut().for_each(|b| *b = 0);
+                    *len -= n as u64;
+                    return Ok(n);
+                }
+                Some(Segment::Data { pos, len }) if *len > 0 => {
+                    let mut inner = &me_seekable.inner;
+                    if inner.pos.get() != *pos {
+                        inner.seek(SeekFrom::Start(*pos))?;
+                    }
+                    let n = cmp::min(*len, into.len() as u64) as usize;
+                    let n = inner.read(&mut into[..n])?;
+                    *pos += n as u64;
+                    *len -= n as u64;
+                    return Ok(n);
+                }
+                Some(_) => self.segment += 1,
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+impl<'b, 'a: 'b, R: 'a + Read, F> Concat<'b, 'a, R, F> {
+    /// Consumes this reader, returning the entry which ended the group, if
+    /// it has been reached.
+    ///
+    /// This is `None` if the contents haven't been read to the end yet, or
+    /// if the archive ran out of entries first.
+    pub fn into_next(self) -> Option<Entry<'a, R>> {
+        self.next
+    }
+}
+
+impl<'b, 'a: 'b, R: 'a + Read, F> Read for Concat<'b, 'a, R, F>
+where
+    F: FnMut(&Entry<'a, R>) -> bool,
+{
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(ref mut current) = self.current {
+                let n = current.read(into)?;
+                if n > 0 || into.is_empty() {
+                    return Ok(n);
+                }
+                self.current = None;
+            }
+            if self.done {
+                return Ok(0);
+            }
+            match self.entries.next() {
+                Some(Ok(entry)) => {
+                    if (self.group)(&entry) {
+                        self.current = Some(entry);
+                    } else {
+                        self.next = Some(entry);
+                        self.done = true;
+                    }
+                }
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Err(e);
+                }
+                None => self.done = true,
+            }
+        }
+    }
+}
+
+impl<'b, 'a: 'b, R: 'a + Read> Deref for BorrowedEntry<'b, 'a, R> {
+    type Target = Entry<'a, R>;
+
+    fn deref(&self) -> &Entry<'a, R> {
+        &self.entry
+    }
+}
+
+impl<'b, 'a: 'b, R: 'a + Read> DerefMut for BorrowedEntry<'b, 'a, R> {
+    fn deref_mut(&mut self) -> &mut Entry<'a, R> {
+        &mut self.entry
+    }
+}
+
+impl<R, H> HashingReader<R, H> {
+    /// Returns a reference to the hasher.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwraps this reader, returning the underlying reader and the hasher.
+    pub fn into_parts(self) -> (R, H) {
+        (self.inner, self.hasher)
+    }
+}
+
+impl<R: Read, H: Write> Read for HashingReader<R, H> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(into)?;
+        self.hasher.write_all(&into[..n])?;
+        Ok(n)
+    }
+}
+
+impl SeekData<'_> {
+    pub fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.left
+    }
+}
+
+impl Read for SeekData<'_> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if self.left == 0 || into.is_empty() {
+            return Ok(0);
+        }
+        let mut archive = self.archive;
+        archive.seek(SeekFrom::Start(self.pos))?;
+        let max = cmp::min(self.left, into.len() as u64) as usize;
+        let n = archive.read(&mut into[..max])?;
+        self.pos += n as u64;
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> ArchiveInner<R> {
+    /// Replaces the underlying reader with `f(obj)`, carrying every option
+    /// and all of the position tracking over unchanged.
+    fn map_obj<S>(self, f: impl FnOnce(R) -> S) -> ArchiveInner<S> {
+        // Listing every field, rather than using `..`, makes new fields fail
+        // to compile here until they are carried over too.
+        let ArchiveInner {
+            pos,
+            start,
+            unpack_xattrs,
+            preserve_permissions,
+            preserve_ownerships,
+            preserve_mtime,
+            overwrite,
+            ignore_zeros,
+            lenient_checksums,
+            reject_empty_paths,
+            max_entries,
+            max_path_len,
+            extract_newer_than,
+            atomic_unpack,
+            always_seek,
+            cancel,
+            base,
+            obj,
+        } = self;
+        ArchiveInner {
+            pos,
+            start,
+            unpack_xattrs,
+            preserve_permissions,
+            preserve_ownerships,
+            preserve_mtime,
+            overwrite,
+            ignore_zeros,
+            lenient_checksums,
+            reject_empty_paths,
+            max_entries,
+            max_path_len,
+            extract_newer_than,
+            atomic_unpack,
+            always_seek,
+            cancel,
+            base,
+            obj: RefCell::new(f(obj.into_inner())),
+        }
+    }
+}
+
+impl<R: ?Sized> ArchiveInner<R> {
+    /// Returns how many bytes into the archive the reader currently is.
+    pub fn offset(&self) -> u64 {
+        self.pos.get() - self.start
+    }
+}
+
 impl<'a, R: ?Sized + Read> Read for &'a ArchiveInner<R> {
     fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if let Some(ref cancel) = self.cancel {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(other("operation cancelled"));
+            }
+        }
         let i = self.obj.borrow_mut().read(into)?;
         self.pos.set(self.pos.get() + i as u64);
         Ok(i)
     }
 }
 
+// Positions are translated so that, like the tracked position, they count
+// from where the reader was when the archive was created.
 impl<'a, R: ?Sized + Seek> Seek for &'a ArchiveInner<R> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        let pos = self.obj.borrow_mut().seek(pos)?;
+        let mut obj = self.obj.borrow_mut();
+        let base = match self.base.get() {
+            Some(base) => base,
+            None => {
+                let base = obj
+                    .seek(SeekFrom::Current(0))?
+                    .checked_sub(self.pos.get())
+                    .ok_or_else(|| other("seek position out of bounds"))?;
+                self.base.set(Some(base));
+                base
+            }
+        };
+        let pos = match pos {
+            SeekFrom::Start(pos) => SeekFrom::Start(
+                base.checked_add(pos)
+                    .ok_or_else(|| other("seek position out of bounds"))?,
+            ),
+            pos => pos,
+        };
+        let pos = obj
+            .seek(pos)?
+            .checked_sub(base)
+            .ok_or_else(|| other("seek position out of bounds"))?;
         self.pos.set(pos);
         Ok(pos)
     }
 }
 
+/// Creates a new, uniquely named directory next to `path` to extract into.
+fn create_sibling_dir(path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| other("destination has no file name"))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+    for i in 0..100 {
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(".tar-tmp-{}-{}", std::process::id(), i));
+        let tmp = parent.join(tmp_name);
+        match fs::create_dir(&tmp) {
+            Ok(()) => return Ok(tmp),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "no unused temporary directory name found",
+    ))
+}
+
+/// Recursively copies the contents of the directory `src` into the existing,
+/// empty directory `dst`.
+fn copy_dir_contents(src: &Path, dst: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let kind = entry.file_type()?;
+        let to = dst.join(entry.file_name());
+        if kind.is_dir() {
+            fs::create_dir(&to)?;
+            copy_dir_contents(&entry.path(), &to)?;
+        } else if kind.is_symlink() {
+            symlink(&fs::read_link(entry.path())?, &to)?;
+        } else {
+            fs::copy(entry.path(), &to)?;
+        }
+    }
+    return fs::set_permissions(dst, fs::metadata(src)?.permissions());
+
+    #[cfg(target_arch = "wasm32")]
+    #[allow(unused_variables)]
+    fn symlink(src: &Path, dst: &Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "Not implemented"))
+    }
+
+    #[cfg(windows)]
+    fn symlink(src: &Path, dst: &Path) -> io::Result<()> {
+        ::std::os::windows::fs::symlink_file(src, dst)
+    }
+
+    #[cfg(unix)]
+    fn symlink(src: &Path, dst: &Path) -> io::Result<()> {
+        ::std::os::unix::fs::symlink(src, dst)
+    }
+}
+
+/// Renames `from` to `to`, failing with `AlreadyExists` if `to` exists.
+///
+/// A plain rename replaces an empty directory at `to` on Unix, which could
+/// have been created by someone else since the destination was last checked.
+/// Where the platform can rename without replacing, that is done atomically;
+/// otherwise `to` is checked once more right before renaming, which narrows
+/// the window for such a race but can't close it.
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(result) = sys_rename_no_replace(from, to) {
+        return result;
+    }
+    if to.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "destination already exists",
+        ));
+    }
+    fs::rename(from, to)
+}
+
+/// Renames `from` to `to` atomically unless `to` exists, returning `None` if
+/// the platform or filesystem doesn't support doing so.
+#[cfg(target_os = "linux")]
+fn sys_rename_no_replace(from: &Path, to: &Path) -> Option<io::Result<()>> {
+    let (from, to) = match (path_cstring(from), path_cstring(to)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return Some(Err(e)),
+    };
+    // Called through `syscall` as older C libraries lack a wrapper.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            from.as_ptr(),
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if ret == 0 {
+        return Some(Ok(()));
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::ENOSYS) | Some(libc::EINVAL) => None,
+        _ => Some(Err(err)),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn sys_rename_no_replace(from: &Path, to: &Path) -> Option<io::Result<()>> {
+    let (from, to) = match (path_cstring(from), path_cstring(to)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return Some(Err(e)),
+    };
+    if unsafe { libc::renamex_np(from.as_ptr(), to.as_ptr(), libc::RENAME_EXCL) } == 0 {
+        return Some(Ok(()));
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::ENOTSUP) | Some(libc::ENOSYS) => None,
+        _ => Some(Err(err)),
+    }
+}
+
+#[cfg(windows)]
+fn sys_rename_no_replace(from: &Path, to: &Path) -> Option<io::Result<()>> {
+    use std::os::windows::ffi::OsStrExt;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn MoveFileExW(existing: *const u16, new: *const u16, flags: u32) -> i32;
+    }
+
+    let wide = |p: &Path| -> Vec<u16> { p.as_os_str().encode_wide().chain(Some(0)).collect() };
+    let (from, to) = (wide(from), wide(to));
+    // Without `MOVEFILE_REPLACE_EXISTING` an existing `to` is never replaced.
+    if unsafe { MoveFileExW(from.as_ptr(), to.as_ptr(), 0) } != 0 {
+        Some(Ok(()))
+    } else {
+        Some(Err(io::Error::last_os_error()))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn sys_rename_no_replace(_from: &Path, _to: &Path) -> Option<io::Result<()>> {
+    None
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn path_cstring(path: &Path) -> io::Result<std::ffi::CString> {
+    use std::os::unix::prelude::*;
+
+    std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| other(&format!("path contains null character: {:?}", e)))
+}
+
+/// Returns whether `err` is the error of a rename across filesystems.
+#[cfg_attr(not(any(unix, windows)), allow(unused_variables))]
+fn is_cross_device(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    return err.raw_os_error() == Some(libc::EXDEV);
+    #[cfg(windows)]
+    return err.raw_os_error() == Some(17); // ERROR_NOT_SAME_DEVICE
+    #[cfg(not(any(unix, windows)))]
+    return false;
+}
+
+/// Computes the checksum of a header as it was read, which is the sum of all
+/// of its bytes with the checksum field itself taken to be all spaces.
+pub fn header_sum(header: &Header) -> u32 {
+    header.as_bytes()[..148]
+        .iter()
+        .chain(&header.as_bytes()[156..])
+        .fold(0, |a, b| a + (*b as u32))
+        + 8 * 32
+}
+
+/// Returns whether any numeric field of `header` is stored in base-256.
+fn uses_base256(header: &Header) -> bool {
+    let old = header.as_old();
+    let mut fields = vec![
+        &old.mode[..],
+        &old.uid[..],
+        &old.gid[..],
+        &old.size[..],
+        &old.mtime[..],
+    ];
+    if let Some(ustar) = header.as_ustar() {
+        fields.extend(&[&ustar.dev_major[..], &ustar.dev_minor[..]]);
+    } else if let Some(gnu) = header.as_gnu() {
+        fields.extend(&[&gnu.dev_major[..], &gnu.dev_minor[..]]);
+    }
+    fields.iter().any(|field| field[0] & 0x80 != 0)
+}
+
 /// Try to fill the buffer from the reader.
 ///
 /// If the reader reaches its end before filling the buffer at all, returns `false`.
@@ -623,7 +2556,10 @@ fn try_read_all<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
                     return Ok(false);
                 }
 
-                return Err(other("failed to read entire block"));
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "failed to read entire block",
+                ));
             }
             n => read += n,
         }
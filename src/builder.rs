@@ -1,10 +1,13 @@
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::io;
 use std::io::prelude::*;
-use std::path::Path;
+use std::io::SeekFrom;
+use std::path::{Component, Path, PathBuf};
 use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::header::{path2bytes, HeaderMode};
+use crate::header::{path2bytes, HeaderFormat, HeaderMode, DETERMINISTIC_TIMESTAMP};
 use crate::{other, EntryType, Header};
 
 /// A structure for building archives
@@ -13,7 +16,10 @@ use crate::{other, EntryType, Header};
 /// arbitrary writer.
 pub struct Builder<W: Write> {
     mode: HeaderMode,
+    format: HeaderFormat,
     follow: bool,
+    clock: Option<Box<dyn Fn() -> u64 + Send + Sync>>,
+    dirs: Option<HashSet<PathBuf>>,
     finished: bool,
     obj: Option<W>,
 }
@@ -25,12 +31,49 @@ impl<W: Write> Builder<W> {
     pub fn new(obj: W) -> Builder<W> {
         Builder {
             mode: HeaderMode::Complete,
+            format: HeaderFormat::Gnu,
             follow: true,
+            clock: None,
+            dirs: None,
             finished: false,
             obj: Some(obj),
         }
     }
 
+    /// Writes a complete, reproducible archive of `entries` to `obj`,
+    /// returning `obj` once the archive has been finished.
+    ///
+    /// Each key of `entries` is the path of a regular file, and each value
+    /// holds its contents and mode. Entries are written in the sorted order
+    /// of the map, with the owner set to root and the modification time set
+    /// to zero, so the same map always produces exactly the same bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    /// use tar::Builder;
+    ///
+    /// let mut entries = BTreeMap::new();
+    /// entries.insert("bin/hello".to_string(), (b"#!/bin/sh\n".to_vec(), 0o755));
+    /// entries.insert("README".to_string(), (b"hello\n".to_vec(), 0o644));
+    ///
+    /// let data = Builder::from_entries(Vec::new(), &entries).unwrap();
+    /// ```
+    pub fn from_entries(obj: W, entries: &BTreeMap<String, (Vec<u8>, u32)>) -> io::Result<W> {
+        let mut ar = Builder::new(obj);
+        for (path, (data, mode)) in entries {
+            let mut header = Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(*mode);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_mtime(0);
+            ar.append_data(&mut header, path, &data[..])?;
+        }
+        ar.into_inner()
+    }
+
     /// Changes the HeaderMode that will be used when reading fs Metadata for
     /// methods that implicitly read metadata for an input Path. Notably, this
     /// does _not_ apply to `append(Header)`.
@@ -38,12 +81,55 @@ impl<W: Write> Builder<W> {
         self.mode = mode;
     }
 
+    /// Changes the header format used for entries added to this archive. The
+    /// builder uses `HeaderFormat::Gnu` by default.
+    ///
+    /// With `HeaderFormat::V7`, the headers created by methods such as
+    /// `append_path` and `append_stream` are strictly V7 ones, and no GNU
+    /// extension entries are ever written: paths and link names must fit in
+    /// their 100-byte fields, and only regular files, hard links, symlinks
+    /// and directories can be added. Anything else results in an error
+    /// rather than an archive the oldest implementations can't read. Headers
+    /// passed to `append`, `append_data` and `append_link` are still written
+    /// as given, so they should be created with `Header::new_old`.
+    pub fn format(&mut self, format: HeaderFormat) {
+        self.format = format;
+    }
+
     /// Follow symlinks, archiving the contents of the file they point to rather
     /// than adding a symlink to the archive. Defaults to true.
     pub fn follow_symlinks(&mut self, follow: bool) {
         self.follow = follow;
     }
 
+    /// Skip directories which have already been added to this archive by
+    /// `append_path`, `append_path_with_name`, `append_dir` or
+    /// `append_dir_all`, for example because of overlapping calls. Paths are
+    /// compared after normalization, so `foo`, `foo/` and `./foo` are the
+    /// same directory. Directories added through `append` or `append_data`
+    /// are never skipped nor recorded. Defaults to false.
+    pub fn skip_duplicate_dirs(&mut self, skip: bool) {
+        if !skip {
+            self.dirs = None;
+        } else if self.dirs.is_none() {
+            self.dirs = Some(HashSet::new());
+        }
+    }
+
+    /// Sets the clock used to get the current time, in seconds since the Unix
+    /// epoch, when an entry needs a default modification time.
+    ///
+    /// This is used by `append_stream`, which has no file to take a time
+    /// from. Freezing the clock makes its output reproducible, for example
+    /// in tests. Note that `HeaderMode::Deterministic` uses a fixed time
+    /// regardless of the clock. The system clock is used by default.
+    pub fn clock<F>(&mut self, clock: F)
+    where
+        F: Fn() -> u64 + Send + Sync + 'static,
+    {
+        self.clock = Some(Box::new(clock));
+    }
+
     /// Gets shared reference to the underlying object.
     pub fn get_ref(&self) -> &W {
         self.obj.as_ref().unwrap()
@@ -157,11 +243,68 @@ impl<W: Write> Builder<W> {
         path: P,
         data: R,
     ) -> io::Result<()> {
-        prepare_header_path(self.get_mut(), header, path.as_ref())?;
+        let format = self.format;
+        prepare_header_path(self.get_mut(), header, path.as_ref(), format)?;
         header.set_cksum();
         self.append(&header, data)
     }
 
+    /// Adds a new regular file entry to this archive, reading exactly `size`
+    /// bytes of its contents from `data`.
+    ///
+    /// The header is built from the `path`, `size` and `mode` given, with the
+    /// owner set to root and the modification time set to the current time
+    /// according to `clock` (or a fixed time in `HeaderMode::Deterministic`).
+    /// Long paths are handled in the same way as `append_data`. This is
+    /// useful when the contents come from a socket or are computed on the fly
+    /// and the size is known up front.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `UnexpectedEof` if `data` yields fewer than
+    /// `size` bytes. The entry will have been partially written at that
+    /// point, so the archive should be discarded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tar::Builder;
+    ///
+    /// let mut data: &[u8] = b"hello";
+    ///
+    /// let mut ar = Builder::new(Vec::new());
+    /// ar.append_stream("hello.txt", 5, 0o644, &mut data).unwrap();
+    /// let data = ar.into_inner().unwrap();
+    /// ```
+    pub fn append_stream<P: AsRef<Path>, R: Read>(
+        &mut self,
+        path: P,
+        size: u64,
+        mode: u32,
+        mut data: R,
+    ) -> io::Result<()> {
+        let mtime = match self.mode {
+            HeaderMode::Deterministic => DETERMINISTIC_TIMESTAMP,
+            _ => match self.clock {
+                Some(ref clock) => clock(),
+                None => SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+            },
+        };
+        let format = self.format;
+        append_stream(
+            self.get_mut(),
+            path.as_ref(),
+            size,
+            mode,
+            mtime,
+            &mut data,
+            format,
+        )
+    }
+
     /// Adds a new link (symbolic or hard) entry to this archive with the specified path and target.
     ///
     /// This function is similar to [`Self::append_data`] which supports long filenames,
@@ -204,12 +347,78 @@ impl<W: Write> Builder<W> {
     }
 
     fn _append_link(&mut self, header: &mut Header, path: &Path, target: &Path) -> io::Result<()> {
-        prepare_header_path(self.get_mut(), header, path)?;
-        prepare_header_link(self.get_mut(), header, target)?;
+        let format = self.format;
+        prepare_header_path(self.get_mut(), header, path, format)?;
+        prepare_header_link(self.get_mut(), header, target, format)?;
         header.set_cksum();
         self.append(&header, std::io::empty())
     }
 
+    /// Adds a PAX extended header entry holding the given records, which then
+    /// apply to the entry appended next.
+    ///
+    /// This allows for example a `linkpath` record to carry a symlink target
+    /// which is too long for the header's 100-byte field, as an alternative
+    /// to the GNU extension entries `append_link` writes. Nothing is written
+    /// if `headers` is empty.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error for any intermittent I/O error
+    /// which occurs when writing, or if the builder's format is
+    /// `HeaderFormat::V7`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tar::{Builder, EntryType, Header};
+    ///
+    /// let mut ar = Builder::new(Vec::new());
+    /// let target = "really/long/path/to/the/target/of/a/symlink/which/does/not/fit/in/one/header";
+    /// ar.append_pax_extensions([("linkpath", target.as_bytes())]).unwrap();
+    ///
+    /// let mut header = Header::new_ustar();
+    /// header.set_entry_type(EntryType::Symlink);
+    /// header.set_path("foo").unwrap();
+    /// header.set_size(0);
+    /// header.set_cksum();
+    /// ar.append(&header, &[][..]).unwrap();
+    /// let data = ar.into_inner().unwrap();
+    /// ```
+    pub fn append_pax_extensions<'key, 'value>(
+        &mut self,
+        headers: impl IntoIterator<Item = (&'key str, &'value [u8])>,
+    ) -> io::Result<()> {
+        if self.format == HeaderFormat::V7 {
+            return Err(other("pax extensions can not be stored in a V7 archive"));
+        }
+
+        // Each record is "<len> <key>=<value>\n", where the length includes
+        // its own digits.
+        let mut data: Vec<u8> = Vec::new();
+        for (key, value) in headers {
+            let rest_len = 3 + key.len() + value.len();
+            let mut len_len = 1;
+            let mut max_len = 10;
+            while rest_len + len_len >= max_len {
+                len_len += 1;
+                max_len *= 10;
+            }
+            write!(data, "{} {}=", rest_len + len_len, key)?;
+            data.extend_from_slice(value);
+            data.push(b'\n');
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let mut header = Header::new_ustar();
+        header.set_size(data.len() as u64);
+        header.set_entry_type(EntryType::XHeader);
+        header.set_cksum();
+        self.append(&header, &data[..])
+    }
+
     /// Adds a file on the local filesystem to this archive.
     ///
     /// This function will open the file specified by `path` and insert the file
@@ -236,8 +445,17 @@ impl<W: Write> Builder<W> {
     /// ```
     pub fn append_path<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
         let mode = self.mode.clone();
+        let format = self.format;
         let follow = self.follow;
-        append_path_with_name(self.get_mut(), path.as_ref(), None, mode, follow)
+        append_path_with_name(
+            self.obj.as_mut().unwrap(),
+            path.as_ref(),
+            None,
+            mode,
+            format,
+            follow,
+            self.dirs.as_mut(),
+        )
     }
 
     /// Adds a file on the local filesystem to this archive under another name.
@@ -274,13 +492,16 @@ impl<W: Write> Builder<W> {
         name: N,
     ) -> io::Result<()> {
         let mode = self.mode.clone();
+        let format = self.format;
         let follow = self.follow;
         append_path_with_name(
-            self.get_mut(),
+            self.obj.as_mut().unwrap(),
             path.as_ref(),
             Some(name.as_ref()),
             mode,
+            format,
             follow,
+            self.dirs.as_mut(),
         )
     }
 
@@ -312,7 +533,8 @@ impl<W: Write> Builder<W> {
     /// ```
     pub fn append_file<P: AsRef<Path>>(&mut self, path: P, file: &mut fs::File) -> io::Result<()> {
         let mode = self.mode.clone();
-        append_file(self.get_mut(), path.as_ref(), file, mode)
+        let format = self.format;
+        append_file(self.get_mut(), path.as_ref(), file, mode, format)
     }
 
     /// Adds a directory to this archive with the given path as the name of the
@@ -349,7 +571,15 @@ impl<W: Write> Builder<W> {
         Q: AsRef<Path>,
     {
         let mode = self.mode.clone();
-        append_dir(self.get_mut(), path.as_ref(), src_path.as_ref(), mode)
+        let format = self.format;
+        append_dir(
+            self.obj.as_mut().unwrap(),
+            path.as_ref(),
+            src_path.as_ref(),
+            mode,
+            format,
+            self.dirs.as_mut(),
+        )
     }
 
     /// Adds a directory and all of its contents (recursively) to this archive
@@ -380,13 +610,16 @@ impl<W: Write> Builder<W> {
         Q: AsRef<Path>,
     {
         let mode = self.mode.clone();
+        let format = self.format;
         let follow = self.follow;
         append_dir_all(
-            self.get_mut(),
+            self.obj.as_mut().unwrap(),
             path.as_ref(),
             src_path.as_ref(),
             mode,
+            format,
             follow,
+            self.dirs.as_mut(),
         )
     }
 
@@ -406,6 +639,64 @@ impl<W: Write> Builder<W> {
     }
 }
 
+impl<W: Write + Seek> Builder<W> {
+    /// Adds a new entry to this archive whose size isn't known up front.
+    ///
+    /// The `header` is written with a placeholder size, then `data` is copied
+    /// into the archive until it is exhausted while counting the bytes
+    /// written. Afterwards the writer is seeked back to patch the size field
+    /// and checksum of the header, and then forward again to the end of the
+    /// entry. This avoids having to buffer a body of unknown length in memory
+    /// or a temporary file, but as a result is only available for seekable
+    /// writers.
+    ///
+    /// The size and checksum of `header` are ignored; all other fields,
+    /// including the path, are written as given.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use tar::{Builder, Header};
+    ///
+    /// let mut header = Header::new_gnu();
+    /// header.set_path("foo").unwrap();
+    /// header.set_mode(0o644);
+    ///
+    /// let mut data: &[u8] = &[1, 2, 3, 4];
+    ///
+    /// let mut ar = Builder::new(Cursor::new(Vec::new()));
+    /// ar.append_streaming(&header, data).unwrap();
+    /// let data = ar.into_inner().unwrap().into_inner();
+    /// ```
+    pub fn append_streaming<R: Read>(&mut self, header: &Header, mut data: R) -> io::Result<()> {
+        let mut header = header.clone();
+        header.set_size(0);
+        header.set_cksum();
+
+        let dst = self.get_mut();
+        let start = dst.stream_position()?;
+        dst.write_all(header.as_bytes())?;
+        let len = io::copy(&mut data, dst)?;
+
+        // Pad with zeros if necessary.
+        let buf = [0; 512];
+        let remaining = 512 - (len % 512);
+        if remaining < 512 {
+            dst.write_all(&buf[..remaining as usize])?;
+        }
+
+        // Go back and fill in the real size of the entry.
+        let end = dst.stream_position()?;
+        header.set_size(len);
+        header.set_cksum();
+        dst.seek(SeekFrom::Start(start))?;
+        dst.write_all(header.as_bytes())?;
+        dst.seek(SeekFrom::Start(end))?;
+        Ok(())
+    }
+}
+
 fn append(mut dst: &mut dyn Write, header: &Header, mut data: &mut dyn Read) -> io::Result<()> {
     dst.write_all(header.as_bytes())?;
     let len = io::copy(&mut data, &mut dst)?;
@@ -420,12 +711,57 @@ fn append(mut dst: &mut dyn Write, header: &Header, mut data: &mut dyn Read) ->
     Ok(())
 }
 
+fn append_stream(
+    dst: &mut dyn Write,
+    path: &Path,
+    size: u64,
+    mode: u32,
+    mtime: u64,
+    data: &mut dyn Read,
+    format: HeaderFormat,
+) -> io::Result<()> {
+    let mut header = new_header(format);
+    prepare_header_path(dst, &mut header, path, format)?;
+    header.set_size(size);
+    header.set_mode(mode);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(mtime);
+    header.set_entry_type(EntryType::Regular);
+    header.set_cksum();
+
+    dst.write_all(header.as_bytes())?;
+    let len = io::copy(&mut data.take(size), dst)?;
+    if len != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "stream for {} ended after {} of {} bytes",
+                path.display(),
+                len,
+                size
+            ),
+        ));
+    }
+
+    // Pad with zeros if necessary.
+    let buf = [0; 512];
+    let remaining = 512 - (len % 512);
+    if remaining < 512 {
+        dst.write_all(&buf[..remaining as usize])?;
+    }
+
+    Ok(())
+}
+
 fn append_path_with_name(
     dst: &mut dyn Write,
     path: &Path,
     name: Option<&Path>,
     mode: HeaderMode,
+    format: HeaderFormat,
     follow: bool,
+    dirs: Option<&mut HashSet<PathBuf>>,
 ) -> io::Result<()> {
     let stat = if follow {
         fs::metadata(path).map_err(|err| {
@@ -444,9 +780,19 @@ fn append_path_with_name(
     };
     let ar_name = name.unwrap_or(path);
     if stat.is_file() {
-        append_fs(dst, ar_name, &stat, &mut fs::File::open(path)?, mode, None)
+        append_fs(
+            dst,
+            ar_name,
+            &stat,
+            &mut fs::File::open(path)?,
+            mode,
+            format,
+            None,
+        )
     } else if stat.is_dir() {
-        append_fs(dst, ar_name, &stat, &mut io::empty(), mode, None)
+        append_dir_once(dirs, ar_name, || {
+            append_fs(dst, ar_name, &stat, &mut io::empty(), mode, format, None)
+        })
     } else if stat.file_type().is_symlink() {
         let link_name = fs::read_link(path)?;
         append_fs(
@@ -455,12 +801,13 @@ fn append_path_with_name(
             &stat,
             &mut io::empty(),
             mode,
+            format,
             Some(&link_name),
         )
     } else {
         #[cfg(unix)]
         {
-            append_special(dst, path, &stat, mode)
+            append_special(dst, path, &stat, mode, format)
         }
         #[cfg(not(unix))]
         {
@@ -475,9 +822,17 @@ fn append_special(
     path: &Path,
     stat: &fs::Metadata,
     mode: HeaderMode,
+    format: HeaderFormat,
 ) -> io::Result<()> {
     use ::std::os::unix::fs::{FileTypeExt, MetadataExt};
 
+    if format == HeaderFormat::V7 {
+        return Err(other(&format!(
+            "{}: special files can not be stored in a V7 archive",
+            path.display()
+        )));
+    }
+
     let file_type = stat.file_type();
     let entry_type;
     if file_type.is_socket() {
@@ -498,7 +853,7 @@ fn append_special(
 
     let mut header = Header::new_gnu();
     header.set_metadata_in_mode(stat, mode);
-    prepare_header_path(dst, &mut header, path)?;
+    prepare_header_path(dst, &mut header, path, format)?;
 
     header.set_entry_type(entry_type);
     let dev_id = stat.rdev();
@@ -518,9 +873,10 @@ fn append_file(
     path: &Path,
     file: &mut fs::File,
     mode: HeaderMode,
+    format: HeaderFormat,
 ) -> io::Result<()> {
     let stat = file.metadata()?;
-    append_fs(dst, path, &stat, file, mode, None)
+    append_fs(dst, path, &stat, file, mode, format, None)
 }
 
 fn append_dir(
@@ -528,9 +884,37 @@ fn append_dir(
     path: &Path,
     src_path: &Path,
     mode: HeaderMode,
+    format: HeaderFormat,
+    dirs: Option<&mut HashSet<PathBuf>>,
+) -> io::Result<()> {
+    append_dir_once(dirs, path, || {
+        let stat = fs::metadata(src_path)?;
+        append_fs(dst, path, &stat, &mut io::empty(), mode, format, None)
+    })
+}
+
+/// Runs `append` unless `path` is already recorded in `dirs` as a directory
+/// added to the archive, recording it only once `append` succeeds. Every
+/// directory is appended when duplicates aren't being skipped.
+fn append_dir_once(
+    dirs: Option<&mut HashSet<PathBuf>>,
+    path: &Path,
+    append: impl FnOnce() -> io::Result<()>,
 ) -> io::Result<()> {
-    let stat = fs::metadata(src_path)?;
-    append_fs(dst, path, &stat, &mut io::empty(), mode, None)
+    let dirs = match dirs {
+        Some(dirs) => dirs,
+        None => return append(),
+    };
+    let path: PathBuf = path
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    if dirs.contains(&path) {
+        return Ok(());
+    }
+    append()?;
+    dirs.insert(path);
+    Ok(())
 }
 
 fn prepare_header(size: u64, entry_type: u8) -> Header {
@@ -548,12 +932,27 @@ fn prepare_header(size: u64, entry_type: u8) -> Header {
     header
 }
 
-fn prepare_header_path(dst: &mut dyn Write, header: &mut Header, path: &Path) -> io::Result<()> {
+fn new_header(format: HeaderFormat) -> Header {
+    match format {
+        HeaderFormat::Gnu => Header::new_gnu(),
+        HeaderFormat::V7 => Header::new_old(),
+    }
+}
+
+fn prepare_header_path(
+    dst: &mut dyn Write,
+    header: &mut Header,
+    path: &Path,
+    format: HeaderFormat,
+) -> io::Result<()> {
     // Try to encode the path directly in the header, but if it ends up not
     // working (probably because it's too long) then try to use the GNU-specific
     // long name extension by emitting an entry which indicates that it's the
     // filename.
     if let Err(e) = header.set_path(path) {
+        if format == HeaderFormat::V7 {
+            return Err(e);
+        }
         let data = path2bytes(&path)?;
         let max = header.as_old().name.len();
         // Since `e` isn't specific enough to let us know the path is indeed too
@@ -584,9 +983,13 @@ fn prepare_header_link(
     dst: &mut dyn Write,
     header: &mut Header,
     link_name: &Path,
+    format: HeaderFormat,
 ) -> io::Result<()> {
     // Same as previous function but for linkname
     if let Err(e) = header.set_link_name(&link_name) {
+        if format == HeaderFormat::V7 {
+            return Err(e);
+        }
         let data = path2bytes(&link_name)?;
         if data.len() < header.as_old().linkname.len() {
             return Err(e);
@@ -604,14 +1007,22 @@ fn append_fs(
     meta: &fs::Metadata,
     read: &mut dyn Read,
     mode: HeaderMode,
+    format: HeaderFormat,
     link_name: Option<&Path>,
 ) -> io::Result<()> {
-    let mut header = Header::new_gnu();
+    let mut header = new_header(format);
 
-    prepare_header_path(dst, &mut header, path)?;
+    if format == HeaderFormat::V7 && meta.is_dir() {
+        // Old implementations only recognize directories by a trailing slash.
+        let mut path = path.as_os_str().to_owned();
+        path.push("/");
+        prepare_header_path(dst, &mut header, Path::new(&path), format)?;
+    } else {
+        prepare_header_path(dst, &mut header, path, format)?;
+    }
     header.set_metadata_in_mode(meta, mode);
     if let Some(link_name) = link_name {
-        prepare_header_link(dst, &mut header, link_name)?;
+        prepare_header_link(dst, &mut header, link_name, format)?;
     }
     header.set_cksum();
     append(dst, &header, read)
@@ -622,7 +1033,9 @@ fn append_dir_all(
     path: &Path,
     src_path: &Path,
     mode: HeaderMode,
+    format: HeaderFormat,
     follow: bool,
+    mut dirs: Option<&mut HashSet<PathBuf>>,
 ) -> io::Result<()> {
     let mut stack = vec![(src_path.to_path_buf(), true, false)];
     while let Some((src, is_dir, is_symlink)) = stack.pop() {
@@ -635,22 +1048,30 @@ fn append_dir_all(
                 stack.push((entry.path(), file_type.is_dir(), file_type.is_symlink()));
             }
             if dest != Path::new("") {
-                append_dir(dst, &dest, &src, mode)?;
+                append_dir(dst, &dest, &src, mode, format, dirs.as_deref_mut())?;
             }
         } else if !follow && is_symlink {
             let stat = fs::symlink_metadata(&src)?;
             let link_name = fs::read_link(&src)?;
-            append_fs(dst, &dest, &stat, &mut io::empty(), mode, Some(&link_name))?;
+            append_fs(
+                dst,
+                &dest,
+                &stat,
+                &mut io::empty(),
+                mode,
+                format,
+                Some(&link_name),
+            )?;
         } else {
             #[cfg(unix)]
             {
                 let stat = fs::metadata(&src)?;
                 if !stat.is_file() {
-                    append_special(dst, &dest, &stat, mode)?;
+                    append_special(dst, &dest, &stat, mode, format)?;
                     continue;
                 }
             }
-            append_file(dst, &dest, &mut fs::File::open(src)?, mode)?;
+            append_file(dst, &dest, &mut fs::File::open(src)?, mode, format)?;
         }
     }
     Ok(())
@@ -1,19 +1,21 @@
 use std::borrow::Cow;
 use std::cmp;
+use std::convert::TryFrom;
 use std::fs;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::io::{self, Error, ErrorKind, SeekFrom};
 use std::marker;
 use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use filetime::{self, FileTime};
 
-use crate::archive::ArchiveInner;
+use crate::archive::{header_sum, ArchiveInner, SeekData};
 use crate::error::TarError;
-use crate::header::bytes2path;
+use crate::header::{bytes2path, SparseEntry, BLOCK_SIZE};
 use crate::other;
-use crate::{Archive, Header, PaxExtensions};
+use crate::{Archive, EntryType, Header, PaxExtensions};
 
 /// A read-only view into an entry of an archive.
 ///
@@ -30,12 +32,17 @@ pub struct Entry<'a, R: 'a + Read> {
 pub struct EntryFields<'a> {
     pub long_pathname: Option<Vec<u8>>,
     pub long_linkname: Option<Vec<u8>>,
+    // Whether GNU long name or long link name entries preceded this one, as
+    // opposed to the long name coming from a pax sparse name.
+    pub gnu_long_names: bool,
     pub pax_extensions: Option<Vec<u8>>,
     pub header: Header,
+    pub checksum_ok: bool,
     pub size: u64,
     pub header_pos: u64,
     pub file_pos: u64,
     pub data: Vec<EntryIo<'a>>,
+    pub archive: &'a ArchiveInner<dyn Read + 'a>,
     pub unpack_xattrs: bool,
     pub preserve_permissions: bool,
     pub preserve_ownerships: bool,
@@ -46,6 +53,37 @@ pub struct EntryFields<'a> {
 pub enum EntryIo<'a> {
     Pad(io::Take<io::Repeat>),
     Data(io::Take<&'a ArchiveInner<dyn Read + 'a>>),
+    Seek(SeekData<'a>),
+}
+
+/// The metadata of an entry needed for a verbose listing, like `tar -tv`.
+///
+/// This is returned by `Entry::info` and `Archive::verbose_list`, leaving the
+/// exact formatting of each field up to the caller.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct EntryInfo {
+    /// The full path of the entry.
+    pub path: PathBuf,
+    /// The target of the entry, if it is a link.
+    pub link_name: Option<PathBuf>,
+    /// The type of the entry.
+    pub entry_type: EntryType,
+    /// The mode of the entry, as stored in its header.
+    pub mode: u32,
+    /// The numeric owner of the entry.
+    pub uid: u64,
+    /// The numeric group of the entry.
+    pub gid: u64,
+    /// The name of the owner of the entry, if recorded.
+    pub username: Option<String>,
+    /// The name of the group of the entry, if recorded.
+    pub groupname: Option<String>,
+    /// The size of the entry, as reported by `Entry::size`.
+    pub size: u64,
+    /// The modification time of the entry, in whole seconds since the Unix
+    /// epoch, as reported by `Entry::modified`.
+    pub mtime: u64,
 }
 
 /// When unpacking items the unpacked thing is returned to allow custom
@@ -87,6 +125,34 @@ impl<'a, R: Read> Entry<'a, R> {
         self.fields.path_bytes()
     }
 
+    /// Returns the path of this entry with redundant components removed.
+    ///
+    /// This is a purely lexical cleanup of `path_bytes`: empty components
+    /// (from duplicate or trailing slashes) and `.` components are dropped,
+    /// while `..` components and a leading `/` are kept as they are. The
+    /// filesystem is never consulted. A path which is empty after cleanup is
+    /// returned as `.`.
+    pub fn normalized_path(&self) -> Vec<u8> {
+        let path = self.path_bytes();
+        let mut normalized = Vec::with_capacity(path.len());
+        if path.starts_with(b"/") {
+            normalized.push(b'/');
+        }
+        for component in path.split(|b| *b == b'/') {
+            if component.is_empty() || component == b"." {
+                continue;
+            }
+            if !normalized.is_empty() && normalized.last() != Some(&b'/') {
+                normalized.push(b'/');
+            }
+            normalized.extend_from_slice(component);
+        }
+        if normalized.is_empty() {
+            normalized.push(b'.');
+        }
+        normalized
+    }
+
     /// Returns the link name for this entry, if any is found.
     ///
     /// This method may fail if the pathname is not valid Unicode and this is
@@ -135,6 +201,25 @@ impl<'a, R: Read> Entry<'a, R> {
         self.fields.pax_extensions()
     }
 
+    /// Returns the modification time of this entry.
+    ///
+    /// A pax `mtime` record describing this entry takes precedence over the
+    /// header, which keeps any sub-second part of the time it records.
+    /// Otherwise the whole seconds stored in the header are used. This is
+    /// the same time that unpacking gives files when `set_preserve_mtime` is
+    /// enabled.
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        let (secs, nanos) = self.fields.mtime()?;
+        let time = if secs >= 0 {
+            UNIX_EPOCH.checked_add(Duration::new(secs as u64, nanos))
+        } else {
+            UNIX_EPOCH
+                .checked_sub(Duration::from_secs(secs.unsigned_abs()))
+                .and_then(|t| t.checked_add(Duration::new(0, nanos)))
+        };
+        time.ok_or_else(|| other("modification time out of range"))
+    }
+
     /// Returns access to the header of this entry in the archive.
     ///
     /// This provides access to the metadata for this entry in the archive.
@@ -146,6 +231,9 @@ impl<'a, R: Read> Entry<'a, R> {
     ///
     /// In the event the size is stored in a pax extension, that size value
     /// will be referenced. Otherwise, the entry size will be stored in the header.
+    ///
+    /// Hard links report the size declared in their header, but never have
+    /// any contents of their own to read.
     pub fn size(&self) -> u64 {
         self.fields.size
     }
@@ -170,6 +258,70 @@ impl<'a, R: Read> Entry<'a, R> {
         self.fields.file_pos
     }
 
+    /// Gathers the metadata of this entry needed for a verbose listing.
+    ///
+    /// Pax `mtime`, `uid`, `gid`, `uname` and `gname` records describing this
+    /// entry take precedence over the header, just like its size. Returns an
+    /// error if any of the numeric fields fail to parse. User and group names
+    /// which aren't valid UTF-8 are converted lossily.
+    pub fn info(&self) -> io::Result<EntryInfo> {
+        let header = self.header();
+        let name = |bytes: Option<&[u8]>| match bytes {
+            Some(b) if !b.is_empty() => Some(String::from_utf8_lossy(b).into_owned()),
+            _ => None,
+        };
+        let fields = &self.fields;
+        let (mtime, _) = fields.mtime()?;
+        Ok(EntryInfo {
+            path: self.path()?.into_owned(),
+            link_name: self.link_name()?.map(|l| l.into_owned()),
+            entry_type: header.entry_type(),
+            mode: header.mode()?,
+            uid: fields.pax_id("uid", header.uid())?,
+            gid: fields.pax_id("gid", header.gid())?,
+            username: name(fields.pax_value(b"uname").or(header.username_bytes())),
+            groupname: name(fields.pax_value(b"gname").or(header.groupname_bytes())),
+            size: self.size(),
+            mtime: u64::try_from(mtime).map_err(|_| other("modification time out of range"))?,
+        })
+    }
+
+    /// Reads the padding which follows the contents of this entry in the
+    /// archive, up to the next 512-byte boundary.
+    ///
+    /// Any contents of this entry which haven't been read yet are skipped
+    /// first. In a well-formed archive the padding is all zeros, but some
+    /// writers leave other data behind there, which can be of interest when
+    /// recovering damaged archives. The padding can only be read once, and an
+    /// empty vector is returned when the contents end on a block boundary.
+    ///
+    /// Like the contents of an entry, the padding can only be read while this
+    /// is the most recent entry returned by the iterator.
+    pub fn padding_bytes(&mut self) -> io::Result<Vec<u8>> {
+        self.fields.padding_bytes()
+    }
+
+    /// Checks that the header of this entry is internally consistent.
+    ///
+    /// This verifies that the numeric fields of the header can be parsed, that
+    /// the checksum matches the header's contents, and that entry types which
+    /// carry no data (directories, symlinks, device files and fifos) don't
+    /// list a size. A descriptive error is returned for the first problem
+    /// found.
+    pub fn validate(&self) -> io::Result<()> {
+        self.fields.validate()
+    }
+
+    /// Returns whether the checksums of this entry's header, and of any long
+    /// name or pax extension headers describing it, matched their contents.
+    ///
+    /// This is always true unless `Archive::set_lenient_checksums` has been
+    /// enabled, in which case entries with a bad checksum are still yielded
+    /// so that they can be reported.
+    pub fn checksum_valid(&self) -> bool {
+        self.fields.checksum_ok
+    }
+
     /// Writes this file to the specified location.
     ///
     /// This function will write the entire contents of this file into the
@@ -246,7 +398,8 @@ impl<'a, R: Read> Entry<'a, R> {
     /// when unpacking this entry.
     ///
     /// This flag is disabled by default and is currently only implemented on
-    /// Unix.
+    /// Unix. In either case, the mode is masked to its permission bits before
+    /// being applied, so any file type bits stored in the mode are ignored.
     pub fn set_preserve_permissions(&mut self, preserve: bool) {
         self.fields.preserve_permissions = preserve;
     }
@@ -278,6 +431,24 @@ impl<'a> EntryFields<'a> {
         }
     }
 
+    /// Returns the length of this entry's contents if they're stored
+    /// contiguously right after its headers, which is the case unless it's
+    /// sparse. This is only accurate before any of them have been read.
+    pub fn contiguous_len(&self) -> Option<u64> {
+        let sparse = self.header.entry_type().is_gnu_sparse()
+            || self.is_pax_sparse()
+            || !matches!(self.pax_sparse_map(), Ok(None));
+        if sparse {
+            return None;
+        }
+        match self.data[..] {
+            [] => Some(0),
+            [EntryIo::Data(ref data)] => Some(data.limit()),
+            [EntryIo::Seek(ref data)] => Some(data.limit()),
+            _ => None,
+        }
+    }
+
     pub fn read_all(&mut self) -> io::Result<Vec<u8>> {
         // Preallocate some data but don't let ourselves get too crazy now.
         let cap = cmp::min(self.size, 128 * 1024);
@@ -285,7 +456,7 @@ impl<'a> EntryFields<'a> {
         self.read_to_end(&mut v).map(|_| v)
     }
 
-    pub fn is_pax_sparse(&mut self) -> bool {
+    pub fn is_pax_sparse(&self) -> bool {
         if let Some(ref pax) = self.pax_extensions {
             let mut extensions = PaxExtensions::new(pax).filter_map(|f| f.ok());
             return extensions
@@ -325,6 +496,71 @@ impl<'a> EntryFields<'a> {
         Err(other("PAX extension GNU.sparse.realsize not found"))
     }
 
+    /// Returns the real size and sparse map of an entry using the PAX sparse
+    /// format 0.0 (`GNU.sparse.offset`/`GNU.sparse.numbytes` pairs) or 0.1
+    /// (a single `GNU.sparse.map` record), or `None` if it uses neither.
+    pub fn pax_sparse_map(&self) -> io::Result<Option<(u64, Vec<SparseEntry>)>> {
+        let pax = match self.pax_extensions {
+            Some(ref pax) => pax,
+            None => return Ok(None),
+        };
+        let parse = |bytes: &[u8]| -> io::Result<u64> {
+            std::str::from_utf8(bytes)
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .ok_or_else(|| other("failed to parse a PAX sparse map value"))
+        };
+        let mut real_size = None;
+        let mut map = None;
+        let mut offset = None;
+        for ext in PaxExtensions::new(pax) {
+            let ext = ext?;
+            match ext.key_bytes() {
+                b"GNU.sparse.size" => real_size = Some(parse(ext.value_bytes())?),
+                b"GNU.sparse.offset" => {
+                    if offset.is_some() {
+                        return Err(other("PAX sparse offset without a length"));
+                    }
+                    offset = Some(parse(ext.value_bytes())?);
+                }
+                b"GNU.sparse.numbytes" => {
+                    let offset = offset
+                        .take()
+                        .ok_or_else(|| other("PAX sparse length without an offset"))?;
+                    let size = parse(ext.value_bytes())?;
+                    map.get_or_insert_with(Vec::new)
+                        .push(SparseEntry { offset, size });
+                }
+                b"GNU.sparse.map" => {
+                    let values = ext
+                        .value_bytes()
+                        .split(|b| *b == b',')
+                        .map(parse)
+                        .collect::<io::Result<Vec<_>>>()?;
+                    if values.len() % 2 != 0 {
+                        return Err(other("odd number of values in PAX sparse map"));
+                    }
+                    let map = map.get_or_insert_with(Vec::new);
+                    for pair in values.chunks(2) {
+                        map.push(SparseEntry {
+                            offset: pair[0],
+                            size: pair[1],
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        if offset.is_some() {
+            return Err(other("PAX sparse offset without a length"));
+        }
+        match (map, real_size) {
+            (Some(map), Some(real_size)) => Ok(Some((real_size, map))),
+            (Some(_), None) => Err(other("PAX extension GNU.sparse.size not found")),
+            (None, _) => Ok(None),
+        }
+    }
+
     fn path(&self) -> io::Result<Cow<Path>> {
         bytes2path(self.path_bytes())
     }
@@ -353,6 +589,58 @@ impl<'a> EntryFields<'a> {
         }
     }
 
+    fn padding_bytes(&mut self) -> io::Result<Vec<u8>> {
+        io::copy(self, &mut io::sink())?;
+        let rem = self.archive.offset() % BLOCK_SIZE as u64;
+        let len = if rem == 0 {
+            0
+        } else {
+            BLOCK_SIZE - rem as usize
+        };
+        let mut padding = vec![0; len];
+        let mut archive = self.archive;
+        archive.read_exact(&mut padding)?;
+        Ok(padding)
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        let header = &self.header;
+        header.mode()?;
+        header.uid()?;
+        header.gid()?;
+        header.mtime()?;
+        header.entry_size()?;
+        let cksum = header.cksum()?;
+        if cksum != header_sum(header) {
+            return Err(other(&format!(
+                "header checksum mismatch for {}",
+                self.path_lossy()
+            )));
+        }
+
+        let kind = header.entry_type();
+        if kind.is_character_special() || kind.is_block_special() {
+            header.device_major()?;
+            header.device_minor()?;
+        }
+        // Hard links may legitimately carry the size of their target, so they
+        // are not checked here.
+        let dataless = kind.is_dir()
+            || kind.is_symlink()
+            || kind.is_character_special()
+            || kind.is_block_special()
+            || kind.is_fifo();
+        if dataless && self.size != 0 {
+            return Err(other(&format!(
+                "{:?} entry {} lists a nonzero size of {}",
+                kind,
+                self.path_lossy(),
+                self.size
+            )));
+        }
+        Ok(())
+    }
+
     /// Gets the path in a "lossy" way, used for error reporting ONLY.
     fn path_lossy(&self) -> String {
         String::from_utf8_lossy(&self.path_bytes()).to_string()
@@ -389,7 +677,47 @@ impl<'a> EntryFields<'a> {
         }
     }
 
-    fn pax_extensions(&mut self) -> io::Result<Option<PaxExtensions>> {
+    /// Returns the value of the pax record `key` describing this entry, if
+    /// there is one.
+    fn pax_value(&self, key: &[u8]) -> Option<&[u8]> {
+        let pax = self.pax_extensions.as_ref()?;
+        PaxExtensions::new(pax)
+            .filter_map(|f| f.ok())
+            .find(|f| f.key_bytes() == key)
+            .map(|f| f.value_bytes())
+    }
+
+    /// Returns the numeric id in the pax record `key`, falling back to
+    /// `header` when there is no such record.
+    fn pax_id(&self, key: &str, header: io::Result<u64>) -> io::Result<u64> {
+        match self.pax_value(key.as_bytes()) {
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| other(&format!("invalid pax {} record", key))),
+            None => header,
+        }
+    }
+
+    /// Returns the modification time as whole seconds since the epoch and
+    /// the nanoseconds past them, preferring a pax `mtime` record over the
+    /// header.
+    fn mtime(&self) -> io::Result<(i64, u32)> {
+        if let Some(ref pax) = self.pax_extensions {
+            let mtime = PaxExtensions::new(pax)
+                .filter_map(|f| f.ok())
+                .find(|f| f.key_bytes() == b"mtime")
+                .and_then(|f| f.value().ok().and_then(parse_pax_time));
+            if let Some(mtime) = mtime {
+                return Ok(mtime);
+            }
+        }
+        let mtime = self.header.mtime()?;
+        let secs = i64::try_from(mtime).map_err(|_| other("modification time out of range"))?;
+        Ok((secs, 0))
+    }
+
+    pub fn pax_extensions(&mut self) -> io::Result<Option<PaxExtensions>> {
         if self.pax_extensions.is_none() {
             if !self.header.entry_type().is_pax_global_extensions()
                 && !self.header.entry_type().is_pax_local_extensions()
@@ -504,16 +832,16 @@ impl<'a> EntryFields<'a> {
             Ok(())
         }
 
-        fn get_mtime(header: &Header) -> Option<FileTime> {
-            header.mtime().ok().map(|mtime| {
+        fn get_mtime(entry: &EntryFields) -> Option<FileTime> {
+            entry.mtime().ok().map(|(secs, nanos)| {
                 // For some more information on this see the comments in
                 // `Header::fill_platform_from`, but the general idea is that
                 // we're trying to avoid 0-mtime files coming out of archives
                 // since some tools don't ingest them well. Perhaps one day
                 // when Cargo stops working with 0-mtime archives we can remove
                 // this.
-                let mtime = if mtime == 0 { 1 } else { mtime };
-                FileTime::from_unix_time(mtime as i64, 0)
+                let secs = if secs == 0 { 1 } else { secs };
+                FileTime::from_unix_time(secs, nanos)
             })
         }
 
@@ -567,17 +895,26 @@ impl<'a> EntryFields<'a> {
                     }
                     None => src.into_owned(),
                 };
-                fs::hard_link(&link_src, dst).map_err(|err| {
-                    Error::new(
-                        err.kind(),
-                        format!(
-                            "{} when hard linking {} to {}",
-                            err,
-                            link_src.display(),
-                            dst.display()
-                        ),
-                    )
-                })?;
+                fs::hard_link(&link_src, dst)
+                    .or_else(|err_io| {
+                        if err_io.kind() == io::ErrorKind::AlreadyExists && self.overwrite {
+                            // remove dest and try once more
+                            std::fs::remove_file(dst).and_then(|()| fs::hard_link(&link_src, dst))
+                        } else {
+                            Err(err_io)
+                        }
+                    })
+                    .map_err(|err| {
+                        Error::new(
+                            err.kind(),
+                            format!(
+                                "{} when hard linking {} to {}",
+                                err,
+                                link_src.display(),
+                                dst.display()
+                            ),
+                        )
+                    })?;
             } else {
                 symlink(&src, dst)
                     .or_else(|err_io| {
@@ -600,7 +937,7 @@ impl<'a> EntryFields<'a> {
                         )
                     })?;
                 if self.preserve_mtime {
-                    if let Some(mtime) = get_mtime(&self.header) {
+                    if let Some(mtime) = get_mtime(self) {
                         filetime::set_symlink_file_times(dst, mtime, mtime).map_err(|e| {
                             TarError::new(format!("failed to set mtime for `{}`", dst.display()), e)
                         })?;
@@ -628,6 +965,7 @@ impl<'a> EntryFields<'a> {
             || kind.is_pax_local_extensions()
             || kind.is_gnu_longname()
             || kind.is_gnu_longlink()
+            || kind.is_gnu_dumpdir()
         {
             return Ok(Unpacked::__Nonexhaustive);
         };
@@ -683,6 +1021,12 @@ impl<'a> EntryFields<'a> {
                             return Err(other("failed to write entire file"));
                         }
                     }
+                    EntryIo::Seek(mut d) => {
+                        let expected = d.limit();
+                        if io::copy(&mut d, &mut f)? != expected {
+                            return Err(other("failed to write entire file"));
+                        }
+                    }
                     EntryIo::Pad(d) => {
                         // TODO: checked cast to i64
                         let to = SeekFrom::Current(d.limit() as i64);
@@ -706,7 +1050,7 @@ impl<'a> EntryFields<'a> {
         })?;
 
         if self.preserve_mtime {
-            if let Some(mtime) = get_mtime(&self.header) {
+            if let Some(mtime) = get_mtime(self) {
                 filetime::set_file_handle_times(&f, Some(mtime), Some(mtime)).map_err(|e| {
                     TarError::new(format!("failed to set mtime for `{}`", dst.display()), e)
                 })?;
@@ -824,7 +1168,14 @@ impl<'a> EntryFields<'a> {
         ) -> io::Result<()> {
             use std::os::unix::prelude::*;
 
-            let mode = if preserve { mode } else { mode & 0o777 };
+            // Some archivers store the full `st_mode` here, including the file
+            // type bits. The type of the file comes from the entry type, not the
+            // mode, so only the permission bits are ever applied.
+            let mode = if preserve {
+                mode & 0o7777
+            } else {
+                mode & 0o777
+            };
             let perm = fs::Permissions::from_mode(mode as _);
             match f {
                 Some(f) => f.set_permissions(perm),
@@ -964,6 +1315,28 @@ impl<'a> EntryFields<'a> {
     }
 }
 
+/// Parses a pax time value, such as `1234.5678` or `-1.5`, into whole seconds
+/// since the epoch and the nanoseconds past them.
+fn parse_pax_time(value: &str) -> Option<(i64, u32)> {
+    let (int, frac) = match value.find('.') {
+        Some(i) => (&value[..i], &value[i + 1..]),
+        None => (value, ""),
+    };
+    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let secs = int.parse::<i64>().ok()?;
+    let mut nanos = 0;
+    for i in 0..9 {
+        nanos = nanos * 10 + frac.as_bytes().get(i).map_or(0, |b| u32::from(b - b'0'));
+    }
+    if int.starts_with('-') && nanos > 0 {
+        Some((secs.checked_sub(1)?, 1_000_000_000 - nanos))
+    } else {
+        Some((secs, nanos))
+    }
+}
+
 impl<'a> Read for EntryFields<'a> {
     fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
         loop {
@@ -983,6 +1356,7 @@ impl<'a> Read for EntryIo<'a> {
         match *self {
             EntryIo::Pad(ref mut io) => io.read(into),
             EntryIo::Data(ref mut io) => io.read(into),
+            EntryIo::Seek(ref mut io) => io.read(into),
         }
     }
 }
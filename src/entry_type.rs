@@ -29,6 +29,8 @@ pub enum EntryType {
     GNULongLink,
     /// GNU extension - sparse file
     GNUSparse,
+    /// GNU extension - directory dump written by incremental backups
+    GNUDumpDir,
     /// Global extended header
     XGlobalHeader,
     /// Extended Header
@@ -62,6 +64,7 @@ impl EntryType {
             b'L' => EntryType::GNULongName,
             b'K' => EntryType::GNULongLink,
             b'S' => EntryType::GNUSparse,
+            b'D' => EntryType::GNUDumpDir,
             b => EntryType::__Nonexhaustive(b),
         }
     }
@@ -82,6 +85,7 @@ impl EntryType {
             EntryType::GNULongName => b'L',
             EntryType::GNULongLink => b'K',
             EntryType::GNUSparse => b'S',
+            EntryType::GNUDumpDir => b'D',
             EntryType::__Nonexhaustive(b) => b,
         }
     }
@@ -181,6 +185,14 @@ impl EntryType {
         self == &EntryType::GNULongLink
     }
 
+    /// Returns whether this type represents a GNU incremental directory dump.
+    ///
+    /// The contents of such an entry list the names found in the directory
+    /// when the backup was made, for use by incremental restores.
+    pub fn is_gnu_dumpdir(&self) -> bool {
+        self == &EntryType::GNUDumpDir
+    }
+
     /// Returns whether this type represents a GNU long name header.
     pub fn is_pax_global_extensions(&self) -> bool {
         self == &EntryType::XGlobalHeader
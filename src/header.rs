@@ -18,6 +18,21 @@ use crate::EntryType;
 
 pub const BLOCK_SIZE: usize = 512;
 
+/// The largest device number that fits in the 8-byte octal device fields.
+const MAX_DEVICE: u32 = 0o7777777;
+
+/// The mtime given to entries in `HeaderMode::Deterministic`.
+///
+/// We could in theory set the mtime to zero here, but not all tools seem to
+/// behave well when ingesting files with a 0 timestamp. For example
+/// rust-lang/cargo#9512 shows that lldb doesn't ingest files with a zero
+/// timestamp correctly.
+///
+/// We just need things to be deterministic here so just pick something that
+/// isn't zero. This time, chosen after careful deliberation, corresponds to
+/// Jul 23, 2006 -- the date of the first commit for what would become Rust.
+pub(crate) const DETERMINISTIC_TIMESTAMP: u64 = 1153704088;
+
 /// Representation of the header of an entry in an archive
 #[repr(C)]
 #[allow(missing_docs)]
@@ -39,6 +54,20 @@ pub enum HeaderMode {
     Deterministic,
 }
 
+/// Declares the format of the headers a `Builder` creates for the entries
+/// added to it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum HeaderFormat {
+    /// GNU headers, using GNU extension entries for paths and link names
+    /// which are too long to fit in the header itself.
+    Gnu,
+
+    /// Pre-POSIX V7 headers, with no magic and no fields beyond the original
+    /// ones, for the oldest implementations of tar.
+    V7,
+}
+
 /// Representation of the header of an entry in an archive
 #[repr(C)]
 #[allow(missing_docs)]
@@ -187,6 +216,49 @@ impl Header {
         header
     }
 
+    /// Creates a new GNU header describing a character device with the given
+    /// device numbers.
+    ///
+    /// The entry type, size and device fields are filled in; all other fields
+    /// are left blank as in `new_gnu`. An error is returned if either number
+    /// is too large for the header's octal fields.
+    pub fn new_char_device(major: u32, minor: u32) -> io::Result<Header> {
+        Header::new_device(EntryType::Char, major, minor)
+    }
+
+    /// Creates a new GNU header describing a block device with the given
+    /// device numbers.
+    ///
+    /// See `new_char_device` for more information.
+    pub fn new_block_device(major: u32, minor: u32) -> io::Result<Header> {
+        Header::new_device(EntryType::Block, major, minor)
+    }
+
+    /// Creates a new GNU header describing a hard link at `path` pointing to
+    /// `target`.
+    ///
+    /// The entry type, size, path and link name are filled in; all other
+    /// fields are left blank as in `new_gnu`. The target is expected to name
+    /// an entry that appears earlier in the same archive, but this is not
+    /// checked here.
+    pub fn new_hardlink<P: AsRef<Path>, T: AsRef<Path>>(path: P, target: T) -> io::Result<Header> {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Link);
+        header.set_size(0);
+        header.set_path(path)?;
+        header.set_link_name(target)?;
+        Ok(header)
+    }
+
+    fn new_device(ty: EntryType, major: u32, minor: u32) -> io::Result<Header> {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(ty);
+        header.set_size(0);
+        header.set_device_major(major)?;
+        header.set_device_minor(minor)?;
+        Ok(header)
+    }
+
     fn is_ustar(&self) -> bool {
         let ustar = unsafe { cast::<_, UstarHeader>(self) };
         ustar.magic[..] == b"ustar\0"[..] && ustar.version[..] == b"00"[..]
@@ -632,8 +704,14 @@ impl Header {
     /// Encodes the value `major` into the dev_major field of this header.
     ///
     /// This function will return an error if this header format cannot encode a
-    /// major device number.
+    /// major device number, or if `major` doesn't fit in the field.
     pub fn set_device_major(&mut self, major: u32) -> io::Result<()> {
+        if major > MAX_DEVICE {
+            return Err(other(&format!(
+                "device major number {} is too large for the header",
+                major
+            )));
+        }
         if let Some(ustar) = self.as_ustar_mut() {
             ustar.set_device_major(major);
             Ok(())
@@ -665,8 +743,14 @@ impl Header {
     /// Encodes the value `minor` into the dev_minor field of this header.
     ///
     /// This function will return an error if this header format cannot encode a
-    /// minor device number.
+    /// minor device number, or if `minor` doesn't fit in the field.
     pub fn set_device_minor(&mut self, minor: u32) -> io::Result<()> {
+        if minor > MAX_DEVICE {
+            return Err(other(&format!(
+                "device minor number {} is too large for the header",
+                minor
+            )));
+        }
         if let Some(ustar) = self.as_ustar_mut() {
             ustar.set_device_minor(minor);
             Ok(())
@@ -756,16 +840,7 @@ impl Header {
                 self.set_mode(meta.mode() as u32);
             }
             HeaderMode::Deterministic => {
-                // We could in theory set the mtime to zero here, but not all
-                // tools seem to behave well when ingesting files with a 0
-                // timestamp. For example rust-lang/cargo#9512 shows that lldb
-                // doesn't ingest files with a zero timestamp correctly.
-                //
-                // We just need things to be deterministic here so just pick
-                // something that isn't zero. This time, chosen after careful
-                // deliberation, corresponds to Jul 23, 2006 -- the date of the
-                // first commit for what would become Rust.
-                self.set_mtime(1153704088);
+                self.set_mtime(DETERMINISTIC_TIMESTAMP);
 
                 self.set_uid(0);
                 self.set_gid(0);
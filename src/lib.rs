@@ -23,12 +23,17 @@
 
 use std::io::{Error, ErrorKind};
 
-pub use crate::archive::{Archive, Entries};
+pub use crate::archive::{
+    Archive, ArchiveStatus, BorrowedEntry, Concat, Entries, FeatureSet, HashingReader, IntoEntries,
+    Names, OwnedEntry, Scan, SliceEntries, TryEntries,
+};
 pub use crate::builder::Builder;
-pub use crate::entry::{Entry, Unpacked};
+pub use crate::entry::{Entry, EntryInfo, Unpacked};
 pub use crate::entry_type::EntryType;
 pub use crate::header::GnuExtSparseHeader;
-pub use crate::header::{GnuHeader, GnuSparseHeader, Header, HeaderMode, OldHeader, UstarHeader};
+pub use crate::header::{
+    GnuHeader, GnuSparseHeader, Header, HeaderFormat, HeaderMode, OldHeader, UstarHeader,
+};
 pub use crate::pax::{PaxExtension, PaxExtensions};
 
 mod archive;
@@ -4,14 +4,16 @@ extern crate tempfile;
 #[cfg(all(unix, feature = "xattr"))]
 extern crate xattr;
 
+use std::cell::RefCell;
 use std::fs::{self, File};
 use std::io::prelude::*;
 use std::io::{self, Cursor};
 use std::iter::repeat;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use filetime::FileTime;
-use tar::{Archive, Builder, Entries, EntryType, Header, HeaderMode};
+use tar::{Archive, Builder, Entries, EntryType, Header, HeaderFormat, HeaderMode};
 use tempfile::{Builder as TempBuilder, TempDir};
 
 macro_rules! t {
@@ -264,22 +266,1539 @@ impl<T: Seek> Seek for LoggingReader<T> {
     }
 }
 
+/// A reader which returns at most one byte per call to `read`.
+struct OneByteReader<R>(R);
+
+impl<R: Read> Read for OneByteReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(1);
+        self.0.read(&mut buf[..len])
+    }
+}
+
+#[test]
+fn reading_with_partial_reads() {
+    let archives = [
+        tar!("reading_files.tar"),
+        tar!("sparse.tar"),
+        tar!("pax_sparse-1.0.tar"),
+        tar!("pax.tar"),
+        tar!("7z_long_path.tar"),
+    ];
+    for data in archives.iter() {
+        let mut ar = Archive::new(Cursor::new(data));
+        let expected = t!(ar.read_all(true));
+        let mut ar = Archive::new(OneByteReader(Cursor::new(data)));
+        assert_eq!(t!(ar.read_all(true)), expected);
+    }
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(OneByteReader(Cursor::new(tar!("reading_files.tar"))));
+    t!(ar.unpack(td.path()));
+    assert_eq!(t!(fs::read(td.path().join("b"))), b"b\n".repeat(11));
+}
+
+#[test]
+fn skipping_entries_with_seek() {
+    let mut reader = LoggingReader::new(Cursor::new(tar!("reading_files.tar")));
+    let mut ar_reader = Archive::new(&mut reader);
+    let files: Vec<_> = t!(ar_reader.entries())
+        .map(|entry| entry.unwrap().path().unwrap().to_path_buf())
+        .collect();
+
+    let mut seekable_reader = LoggingReader::new(Cursor::new(tar!("reading_files.tar")));
+    let mut ar_seekable_reader = Archive::new(&mut seekable_reader);
+    let files_seekable: Vec<_> = t!(ar_seekable_reader.entries_with_seek())
+        .map(|entry| entry.unwrap().path().unwrap().to_path_buf())
+        .collect();
+
+    assert!(files == files_seekable);
+    assert!(seekable_reader.read_bytes < reader.read_bytes);
+}
+
+#[test]
+fn validate_entries() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    t!(ar.verify());
+
+    let mut ar = Builder::new(Vec::new());
+    let mut header = Header::new_gnu();
+    header.set_path("dir").unwrap();
+    header.set_mode(0o755);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    header.set_entry_type(EntryType::Directory);
+    header.set_size(0);
+    header.set_cksum();
+    t!(ar.append(&header, io::empty()));
+    header.set_path("link").unwrap();
+    header.set_entry_type(EntryType::Symlink);
+    header.set_size(5);
+    header.set_cksum();
+    t!(ar.append(&header, &b"aaaaa"[..]));
+    header.set_path("bad-mode").unwrap();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(0);
+    header.as_old_mut().mode = *b"notoct\0\0";
+    header.set_cksum();
+    t!(ar.append(&header, io::empty()));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let mut entries = t!(ar.entries());
+    t!(t!(entries.next().unwrap()).validate());
+    let err = t!(entries.next().unwrap()).validate().unwrap_err();
+    assert!(err.to_string().contains("link"), "{}", err);
+    assert!(t!(entries.next().unwrap()).validate().is_err());
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    assert!(ar.verify().is_err());
+}
+
+#[test]
+fn append_streaming_patches_size() {
+    let mut ar = Builder::new(Cursor::new(Vec::new()));
+    let mut header = Header::new_gnu();
+    t!(header.set_path("first"));
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    t!(ar.append_streaming(&header, &[b'x'; 1000][..]));
+    t!(header.set_path("second"));
+    t!(ar.append_streaming(&header, &b"hello"[..]));
+    let data = t!(ar.into_inner()).into_inner();
+    assert_eq!(data.len(), 512 + 1024 + 512 + 512 + 1024);
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    t!(ar.verify());
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let files = t!(ar.read_all(false));
+    assert_eq!(files[0].0, b"first");
+    assert_eq!(files[0].1, vec![b'x'; 1000]);
+    assert_eq!(files[1].0, b"second");
+    assert_eq!(files[1].1, b"hello");
+}
+
+#[test]
+fn append_stream() {
+    let long_path = PathBuf::from("abcd".repeat(50));
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("short", 4, 0o600, &b"abcdefgh"[..]));
+    t!(ar.append_stream(&long_path, 3, 0o644, &b"xyz"[..]));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(data));
+    let mut entries = t!(ar.entries());
+    let mut e = t!(entries.next().unwrap());
+    assert_eq!(t!(e.path()), Path::new("short"));
+    assert_eq!(t!(e.header().mode()), 0o600);
+    assert!(t!(e.header().mtime()) > 0);
+    let mut s = String::new();
+    t!(e.read_to_string(&mut s));
+    assert_eq!(s, "abcd");
+    let mut e = t!(entries.next().unwrap());
+    assert_eq!(t!(e.path()), long_path);
+    let mut s = String::new();
+    t!(e.read_to_string(&mut s));
+    assert_eq!(s, "xyz");
+    assert!(entries.next().is_none());
+
+    let mut ar = Builder::new(Vec::new());
+    let err = ar
+        .append_stream("short", 10, 0o644, &b"eh"[..])
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn global_pax() {
+    let mut ar = Archive::new(Cursor::new(tar!("pax_global.tar")));
+    let records = t!(ar.global_pax());
+    assert_eq!(records, [(b"comment".to_vec(), b"hello world".to_vec())]);
+    let mut entries = t!(ar.entries());
+    let global = t!(entries.next().unwrap());
+    assert!(global.header().entry_type().is_pax_global_extensions());
+    assert_eq!(&*t!(entries.next().unwrap()).path_bytes(), b"a");
+    assert!(entries.next().is_none());
+
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    assert!(t!(ar.global_pax()).is_empty());
+    assert_eq!(t!(ar.entries()).count(), 2);
+}
+
+#[test]
+fn pax_comment() {
+    let mut ar = Archive::new(Cursor::new(tar!("pax_global.tar")));
+    assert_eq!(t!(ar.comment()).as_deref(), Some("hello world"));
+    assert_eq!(t!(ar.entries()).count(), 2);
+
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    assert_eq!(t!(ar.comment()), None);
+}
+
+#[test]
+fn into_entries() {
+    fn open(data: Vec<u8>) -> tar::IntoEntries<Cursor<Vec<u8>>> {
+        t!(Archive::new(Cursor::new(data)).into_entries())
+    }
+
+    let mut entries = t!(open(tar!("reading_files.tar").to_vec()).collect::<io::Result<Vec<_>>>());
+    assert_eq!(entries.len(), 2);
+    let mut s = String::new();
+    t!(entries[1].read_to_string(&mut s));
+    assert_eq!(s, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+    assert_eq!(entries[0].path_bytes(), b"a");
+    let mut s = String::new();
+    t!(entries[0].read_to_string(&mut s));
+    assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+
+    // Sparse entries read the same as through a borrowing iterator.
+    let mut ar = Archive::new(Cursor::new(tar!("sparse.tar")));
+    let expected = t!(ar.read_all(true));
+    let mut actual = Vec::new();
+    for entry in open(tar!("sparse.tar").to_vec()) {
+        let mut entry = t!(entry);
+        let mut data = Vec::new();
+        t!(entry.read_to_end(&mut data));
+        actual.push((entry.path_bytes().to_vec(), data));
+    }
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn mixed_v7_and_ustar() {
+    let rdr = Cursor::new(tar!("mixed_v7_ustar.tar"));
+    let mut ar = Archive::new(rdr);
+    let mut entries = t!(ar.entries());
+
+    // The first header has no magic, so the junk where a ustar header would
+    // keep its prefix and user names must be ignored.
+    let mut a = t!(entries.next().unwrap());
+    assert!(a.header().as_ustar().is_none());
+    assert!(a.header().as_gnu().is_none());
+    assert_eq!(&*a.path_bytes(), b"v7");
+    assert_eq!(t!(a.header().username()), None);
+    assert_eq!(t!(a.header().groupname()), None);
+    assert_eq!(t!(a.header().device_major()), None);
+    let mut s = String::new();
+    t!(a.read_to_string(&mut s));
+    assert_eq!(s, "old\n");
+
+    let mut b = t!(entries.next().unwrap());
+    assert!(b.header().as_ustar().is_some());
+    assert_eq!(&*b.path_bytes(), b"ustar");
+    assert_eq!(t!(b.header().username()), Some("alice"));
+    assert_eq!(t!(b.header().groupname()), Some("staff"));
+    let mut s = String::new();
+    t!(b.read_to_string(&mut s));
+    assert_eq!(s, "new\n");
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn content_digest() {
+    fn build(mtime: u64, mode: u32, body: &[u8]) -> Vec<u8> {
+        let mut ar = Builder::new(Vec::new());
+        for (path, data) in [("a", body), ("dir/b", &b"bbb"[..])].iter() {
+            let mut header = Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mtime(mtime);
+            header.set_mode(mode);
+            t!(ar.append_data(&mut header, path, *data));
+        }
+        t!(ar.into_inner())
+    }
+    fn digest(data: &[u8]) -> Vec<u8> {
+        t!(Archive::new(data).content_digest(Vec::new()))
+    }
+
+    let base = digest(&build(0, 0o644, b"aa"));
+    assert_eq!(base, digest(&build(1_000_000, 0o755, b"aa")));
+    assert_ne!(base, digest(&build(0, 0o644, b"ab")));
+    assert_ne!(base, digest(&build(0, 0o644, b"aaa")));
+
+    // Every entry of a sparse archive is streamed through in full.
+    let mut ar = Archive::new(Cursor::new(tar!("sparse.tar")));
+    let total: usize = t!(ar.read_all(true)).iter().map(|(_, d)| d.len()).sum();
+    let sparse = digest(tar!("sparse.tar"));
+    assert!(sparse.len() > total);
+
+    // Truncating the archive inside an entry is reported.
+    let data = build(0, 0o644, &[b'x'; 2000]);
+    let mut ar = Archive::new(&data[..1024]);
+    let err = ar.content_digest(Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn builder_from_entries() {
+    use std::collections::BTreeMap;
+
+    let long_path = "abcd/".repeat(30) + "file";
+    let mut entries = BTreeMap::new();
+    entries.insert("b".to_string(), (b"bbb".to_vec(), 0o644));
+    entries.insert("a/x".to_string(), (b"xx".to_vec(), 0o755));
+    entries.insert(long_path.clone(), (Vec::new(), 0o600));
+    let data = t!(Builder::from_entries(Vec::new(), &entries));
+    assert_eq!(data, t!(Builder::from_entries(Vec::new(), &entries)));
+
+    let mut ar = Archive::new(&data[..]);
+    let mut seen = Vec::new();
+    for entry in t!(ar.entries()) {
+        let mut entry = t!(entry);
+        assert_eq!(t!(entry.header().mtime()), 0);
+        assert_eq!(t!(entry.header().uid()), 0);
+        assert_eq!(t!(entry.header().gid()), 0);
+        let mode = t!(entry.header().mode());
+        let mut body = Vec::new();
+        t!(entry.read_to_end(&mut body));
+        seen.push((
+            String::from_utf8(entry.path_bytes().into_owned()).unwrap(),
+            (body, mode),
+        ));
+    }
+    assert_eq!(seen, entries.into_iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn builder_v7_format() {
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let src = td.path().join("src");
+    t!(fs::create_dir_all(src.join("dir")));
+    t!(fs::write(src.join("dir/file"), b"hello"));
+
+    let mut ar = Builder::new(Vec::new());
+    ar.format(HeaderFormat::V7);
+    t!(ar.append_stream("stream", 3, 0o644, &b"abc"[..]));
+    t!(ar.append_dir_all("top", &src));
+    let long_path = "abcd/".repeat(30);
+    assert!(ar.append_stream(&long_path, 0, 0o644, io::empty()).is_err());
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(&data[..]);
+    let mut names = Vec::new();
+    for entry in t!(ar.entries()) {
+        let entry = t!(entry);
+        assert!(entry.header().as_ustar().is_none());
+        assert!(entry.header().as_gnu().is_none());
+        names.push(String::from_utf8(entry.path_bytes().into_owned()).unwrap());
+    }
+    names.sort();
+    assert_eq!(names, ["stream", "top/", "top/dir/", "top/dir/file"]);
+
+    let dst = td.path().join("dst");
+    let mut ar = Archive::new(&data[..]);
+    t!(ar.unpack(&dst));
+    assert_eq!(t!(fs::read(dst.join("stream"))), b"abc");
+    assert_eq!(t!(fs::read(dst.join("top/dir/file"))), b"hello");
+    assert!(dst.join("top/dir").is_dir());
+}
+
+#[test]
+fn builder_clock() {
+    let build = || {
+        let mut ar = Builder::new(Vec::new());
+        ar.clock(|| 1_600_000_000);
+        t!(ar.append_stream("a", 1, 0o644, &b"a"[..]));
+        t!(ar.into_inner())
+    };
+    let data = build();
+    assert_eq!(data, build());
+
+    let mut ar = Archive::new(&data[..]);
+    let e = t!(t!(ar.entries()).next().unwrap());
+    assert_eq!(t!(e.header().mtime()), 1_600_000_000);
+
+    // Deterministic mode keeps its fixed time.
+    let mut ar = Builder::new(Vec::new());
+    ar.clock(|| 1_600_000_000);
+    ar.mode(HeaderMode::Deterministic);
+    t!(ar.append_stream("a", 1, 0o644, &b"a"[..]));
+    let data = t!(ar.into_inner());
+    let mut ar = Archive::new(&data[..]);
+    let e = t!(t!(ar.entries()).next().unwrap());
+    assert_eq!(t!(e.header().mtime()), 1153704088);
+}
+
+#[test]
+fn unpack_layers_into_same_dir() {
+    fn layer(files: &[(&str, &str)], link: (&str, &str)) -> Vec<u8> {
+        let mut ar = Builder::new(Vec::new());
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        t!(ar.append_data(&mut header, "d", io::empty()));
+        for (path, contents) in files {
+            let mut header = Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            t!(ar.append_data(&mut header, path, contents.as_bytes()));
+        }
+        let mut header = t!(Header::new_hardlink(link.0, link.1));
+        header.set_cksum();
+        t!(ar.append(&header, io::empty()));
+        t!(ar.into_inner())
+    }
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let first = layer(&[("d/a", "one")], ("d/h", "d/a"));
+    let second = layer(&[("d/a", "two"), ("d/b", "new")], ("d/h", "d/b"));
+
+    t!(Archive::new(&first[..]).unpack(td.path()));
+    t!(Archive::new(&second[..]).unpack(td.path()));
+    assert_eq!(t!(fs::read_to_string(td.path().join("d/a"))), "two");
+    assert_eq!(t!(fs::read_to_string(td.path().join("d/b"))), "new");
+    assert_eq!(t!(fs::read_to_string(td.path().join("d/h"))), "new");
+
+    // Without overwriting, existing files are an error but existing
+    // directories are not.
+    let mut ar = Archive::new(&first[..]);
+    ar.set_overwrite(false);
+    let err = ar.unpack(td.path()).unwrap_err();
+    assert!(err.to_string().contains("d/a") || err.to_string().contains("d\\a"));
+    assert_eq!(t!(fs::read_to_string(td.path().join("d/a"))), "two");
+}
+
+#[test]
+fn seek_reader_moved_externally() {
+    struct Shared(Rc<RefCell<Cursor<Vec<u8>>>>);
+    impl Read for Shared {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.borrow_mut().read(buf)
+        }
+    }
+    impl Seek for Shared {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.0.borrow_mut().seek(pos)
+        }
+    }
+
+    // The archive doesn't start at the beginning of the reader, and offsets
+    // are counted from where the reader was.
+    let mut data = b"prefix".to_vec();
+    let mut ar = Builder::new(&mut data);
+    t!(ar.append_stream("a", 3, 0o644, &b"foo"[..]));
+    t!(ar.append_stream("b", 3, 0o644, &b"bar"[..]));
+    t!(ar.append_stream("c", 3, 0o644, &b"baz"[..]));
+    t!(ar.into_inner());
+    let expected = [
+        (PathBuf::from("a"), "foo".to_string()),
+        (PathBuf::from("b"), "bar".to_string()),
+        (PathBuf::from("c"), "baz".to_string()),
+    ];
+    let shared = || {
+        let mut cursor = Cursor::new(data.clone());
+        cursor.set_position(6);
+        Rc::new(RefCell::new(cursor))
+    };
+
+    // Moving the reader between entries is fine.
+    let cursor = shared();
+    let mut ar = Archive::new(Shared(cursor.clone()));
+    let mut seen = Vec::new();
+    for entry in t!(ar.entries_with_seek()) {
+        let mut entry = t!(entry);
+        let mut s = String::new();
+        t!(entry.read_to_string(&mut s));
+        seen.push((t!(entry.path()).into_owned(), s));
+
+        // Someone else sharing the reader moves it behind our back.
+        t!(cursor.borrow_mut().seek(io::SeekFrom::Start(1)));
+    }
+    assert_eq!(seen, expected);
+
+    // Moving it in the middle of an entry's contents needs `set_always_seek`.
+    let cursor = shared();
+    let mut ar = Archive::new(Shared(cursor.clone()));
+    ar.set_always_seek(true);
+    let mut seen = Vec::new();
+    for entry in t!(ar.entries_with_seek()) {
+        let mut entry = t!(entry);
+        let mut s = [0; 3];
+        t!(entry.read_exact(&mut s[..1]));
+        t!(cursor.borrow_mut().seek(io::SeekFrom::Start(1)));
+        t!(entry.read_exact(&mut s[1..]));
+        let s = String::from_utf8(s.to_vec()).unwrap();
+        seen.push((t!(entry.path()).into_owned(), s));
+        t!(cursor.borrow_mut().seek(io::SeekFrom::Start(1)));
+    }
+    assert_eq!(seen, expected);
+
+    // Unpacking reads the contents through the same seeks.
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Shared(shared()));
+    ar.set_always_seek(true);
+    for entry in t!(ar.entries_with_seek()) {
+        t!(t!(entry).unpack_in(td.path()));
+    }
+    assert_eq!(t!(fs::read_to_string(td.path().join("c"))), "baz");
+}
+
+#[test]
+fn next_borrowed() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    let mut entries = t!(ar.entries());
+    let mut seen = Vec::new();
+    while let Some(entry) = entries.next_borrowed() {
+        let mut entry = t!(entry);
+        let mut s = String::new();
+        t!(entry.read_to_string(&mut s));
+        seen.push((t!(entry.path()).into_owned(), s));
+    }
+    assert_eq!(
+        seen,
+        [
+            (PathBuf::from("a"), "a\n".repeat(11)),
+            (PathBuf::from("b"), "b\n".repeat(11)),
+        ]
+    );
+}
+
+#[test]
+fn sniff() {
+    struct CountingReader<R> {
+        inner: R,
+        read: u64,
+    }
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n as u64;
+            Ok(n)
+        }
+    }
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("big", 100_000, 0o644, &[0x7f; 100_000][..]));
+    t!(ar.append_stream("small", 2, 0o644, &b"hi"[..]));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(CountingReader {
+        inner: Cursor::new(&data[..]),
+        read: 0,
+    });
+    let sniffed = t!(ar.sniff(4));
+    assert_eq!(
+        sniffed,
+        [
+            (b"big".to_vec(), vec![0x7f; 4]),
+            (b"small".to_vec(), b"hi".to_vec()),
+        ]
+    );
+    assert!(ar.into_inner().read < 10_000);
+}
+
+#[test]
+fn reading_first_entry() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    {
+        let mut a = t!(ar.first()).unwrap();
+        assert_eq!(&*a.path_bytes(), b"a");
+        let mut s = String::new();
+        t!(a.read_to_string(&mut s));
+        assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+    }
+    assert!(ar.first().is_err());
+
+    let mut ar = Archive::new(Cursor::new(vec![0; 1024]));
+    assert!(t!(ar.first()).is_none());
+}
+
+#[test]
+fn reading_entry_larger_than_buffer() {
+    // Entry sizes are tracked as `u64` and only the clamped length of each
+    // read is narrowed. Truncating the remaining size to 32 bits first would
+    // leave just 10 bytes here.
+    let size = (1 << 32) + 10;
+    let mut header = Header::new_gnu();
+    t!(header.set_path("huge"));
+    header.set_mode(0o644);
+    header.set_size(size);
+    header.set_cksum();
+    let data = Cursor::new(header.as_bytes().to_vec()).chain(io::repeat(0x5a));
+
+    let mut ar = Archive::new(data);
+    let mut entries = t!(ar.entries());
+    let mut e = t!(entries.next().unwrap());
+    assert_eq!(e.size(), size);
+    let mut buf = [0; 64];
+    assert_eq!(t!(e.read(&mut buf)), 64);
+    assert_eq!(&buf[..], &[0x5a; 64][..]);
+    assert_eq!(t!(e.read(&mut buf)), 64);
+}
+
+#[test]
+fn reading_all_into_memory() {
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("a", 1, 0o644, &b"a"[..]));
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Directory);
+    header.set_size(0);
+    t!(ar.append_data(&mut header, "dir", io::empty()));
+    t!(ar.append_stream("dir/b", 2, 0o644, &b"bb"[..]));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let files = t!(ar.read_all(false));
+    assert_eq!(
+        files,
+        [
+            (b"a".to_vec(), b"a".to_vec()),
+            (b"dir/b".to_vec(), b"bb".to_vec())
+        ]
+    );
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let files = t!(ar.read_all(true));
+    let names = files.iter().map(|f| &f.0[..]).collect::<Vec<_>>();
+    assert_eq!(names, [&b"a"[..], b"dir", b"dir/b"]);
+    assert!(files[1].1.is_empty());
+}
+
+#[test]
+fn unpack_verified() {
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("good", 4, 0o644, &b"good"[..]));
+    let mut header = Header::new_gnu();
+    header.set_mode(0o755);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    header.set_entry_type(EntryType::Directory);
+    header.set_size(3);
+    t!(ar.append_data(&mut header, "bad", &b"bad"[..]));
+    let data = t!(ar.into_inner());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let results = t!(ar.unpack_verified(td.path(), false));
+    assert_eq!(
+        results,
+        [(PathBuf::from("good"), true), (PathBuf::from("bad"), false)]
+    );
+    assert!(td.path().join("good").is_file());
+    assert!(!td.path().join("bad").exists());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    t!(ar.unpack_verified(td.path(), true));
+    assert!(td.path().join("bad").is_dir());
+
+    // A corrupted checksum is reported rather than aborting the unpack.
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("good", 4, 0o644, &b"good"[..]));
+    t!(ar.append_stream("bad", 3, 0o644, &b"bad"[..]));
+    let mut data = t!(ar.into_inner());
+    data[1024 + 148] ^= 1;
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let results = t!(ar.unpack_verified(td.path(), false));
+    assert_eq!(
+        results,
+        [(PathBuf::from("good"), true), (PathBuf::from("bad"), false)]
+    );
+    assert!(td.path().join("good").is_file());
+    assert!(!td.path().join("bad").exists());
+
+    // The other unpacking options are honored too.
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_extract_newer_than(Some(1 << 40));
+    let results = t!(ar.unpack_verified(td.path(), true));
+    assert_eq!(results.len(), 2);
+    assert_eq!(t!(fs::read_dir(td.path())).count(), 0);
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_atomic_unpack(true);
+    let err = ar.unpack_verified(td.path(), false).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_atomic_unpack(true);
+    t!(ar.unpack_verified(td.path().join("out"), false));
+    assert!(td.path().join("out/good").is_file());
+    assert_eq!(t!(fs::read_dir(td.path())).count(), 1);
+}
+
+#[test]
+fn hardlink_with_size() {
+    let mut ar = Archive::new(Cursor::new(tar!("hardlink_with_size.tar")));
+    let mut entries = t!(ar.entries());
+
+    let a = t!(entries.next().unwrap());
+    assert_eq!(&*a.path_bytes(), b"a");
+
+    let mut b = t!(entries.next().unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+    assert!(b.header().entry_type().is_hard_link());
+    assert_eq!(b.size(), 6);
+    let mut s = Vec::new();
+    t!(b.read_to_end(&mut s));
+    assert!(s.is_empty());
+
+    let mut c = t!(entries.next().unwrap());
+    assert_eq!(&*c.path_bytes(), b"c");
+    let mut s = String::new();
+    t!(c.read_to_string(&mut s));
+    assert_eq!(s, "world\n");
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn new_hardlink_round_trip() {
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("a", 5, 0o644, &b"hello"[..]));
+    let mut header = t!(Header::new_hardlink("b", "a"));
+    header.set_mode(0o644);
+    header.set_cksum();
+    t!(ar.append(&header, io::empty()));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let mut entries = t!(ar.entries());
+    t!(entries.next().unwrap());
+    let b = t!(entries.next().unwrap());
+    assert!(b.header().entry_type().is_hard_link());
+    assert_eq!(b.size(), 0);
+    assert_eq!(&*t!(b.link_name()).unwrap(), Path::new("a"));
+    assert!(entries.next().is_none());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    t!(ar.unpack(td.path()));
+    assert_eq!(t!(fs::read(td.path().join("b"))), b"hello");
+}
+
+#[test]
+fn lenient_checksums() {
+    let mut data = tar!("reading_files.tar").to_vec();
+    // Corrupt the checksum of the first header.
+    data[148] ^= 1;
+
+    let mut ar = Archive::new(&data[..]);
+    assert!(t!(ar.entries()).next().unwrap().is_err());
+
+    let mut ar = Archive::new(&data[..]);
+    ar.set_lenient_checksums(true);
+    let valid = t!(ar.entries())
+        .map(|e| t!(e).checksum_valid())
+        .collect::<Vec<_>>();
+    assert_eq!(valid, [false, true]);
+
+    // The checksum of a long name header counts towards its entry.
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("abcd/".repeat(30), 0, 0o644, io::empty()));
+    let mut data = t!(ar.into_inner());
+    data[148] ^= 1;
+    let mut ar = Archive::new(&data[..]);
+    ar.set_lenient_checksums(true);
+    let e = t!(t!(ar.entries()).next().unwrap());
+    assert_eq!(e.path_bytes().len(), 150);
+    assert!(!e.checksum_valid());
+}
+
+#[test]
+fn concat_split_entries() {
+    let mut ar = Builder::new(Vec::new());
+    for (path, data) in [("big.part0", "a".repeat(700)), ("big.part1", "b".repeat(3))].iter() {
+        t!(ar.append_stream(path, data.len() as u64, 0o644, data.as_bytes()));
+    }
+    t!(ar.append_stream("other", 3, 0o644, &b"ccc"[..]));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(&data[..]);
+    let mut entries = t!(ar.entries());
+    let mut concat = entries.concat(|e| e.path_bytes().starts_with(b"big.part"));
+    let mut s = String::new();
+    t!(concat.read_to_string(&mut s));
+    assert_eq!(s, "a".repeat(700) + "bbb");
+
+    let mut next = concat.into_next().unwrap();
+    assert_eq!(&*next.path_bytes(), b"other");
+    let mut s = String::new();
+    t!(next.read_to_string(&mut s));
+    assert_eq!(s, "ccc");
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn archive_status() {
+    use tar::ArchiveStatus;
+
+    fn status(data: &[u8]) -> ArchiveStatus {
+        t!(Archive::new(data).status())
+    }
+
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("a", 1000, 0o644, &[b'a'; 1000][..]));
+    t!(ar.append_stream("b", 3, 0o644, &b"bbb"[..]));
+    let data = t!(ar.into_inner());
+
+    assert_eq!(status(&data), ArchiveStatus::Complete);
+    assert_eq!(status(&[0; 1024]), ArchiveStatus::Empty);
+    assert_eq!(status(&[]), ArchiveStatus::Truncated);
+    // In the middle of the first entry's contents.
+    assert_eq!(status(&data[..1000]), ArchiveStatus::Truncated);
+    // Between the two entries.
+    assert_eq!(status(&data[..1536]), ArchiveStatus::Truncated);
+    // In the middle of the second header.
+    assert_eq!(status(&data[..1700]), ArchiveStatus::Truncated);
+    // Before the terminator.
+    assert_eq!(status(&data[..2560]), ArchiveStatus::Truncated);
+
+    let mut ar = Archive::new(&data[..]);
+    ar.set_ignore_zeros(true);
+    assert_eq!(t!(ar.status()), ArchiveStatus::Complete);
+
+    let mut bad = data.clone();
+    bad[148] ^= 1;
+    assert!(Archive::new(&bad[..]).status().is_err());
+}
+
+#[test]
+fn features_used() {
+    let features = t!(Archive::new(tar!("reading_files.tar")).features_used());
+    assert_eq!(features, Default::default());
+
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("a".repeat(200), 1, 0o644, &b"a"[..]));
+    t!(ar.append_pax_extensions([("comment", &b"hi"[..])]));
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Char);
+    header.set_size(0);
+    header.set_uid(1 << 40);
+    t!(ar.append_data(&mut header, "dev", io::empty()));
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::new(b'V'));
+    header.set_size(0);
+    t!(ar.append_data(&mut header, "label", io::empty()));
+    let data = t!(ar.into_inner());
+
+    let features = t!(Archive::new(&data[..]).features_used());
+    assert!(features.gnu_long_names);
+    assert!(features.pax);
+    assert!(!features.sparse);
+    assert!(features.base256);
+    assert!(features.device_nodes);
+    assert!(features.multi_volume);
+
+    let features = t!(Archive::new(tar!("sparse.tar")).features_used());
+    assert!(features.sparse);
+    assert!(!features.pax);
+    assert!(!features.gnu_long_names);
+    for data in [tar!("pax_sparse-0.1.tar"), tar!("pax_sparse-1.0.tar")] {
+        let features = t!(Archive::new(data).features_used());
+        assert!(features.sparse);
+        assert!(features.pax);
+        assert!(!features.gnu_long_names);
+    }
+}
+
+#[test]
+fn find_contains_and_unpack_with() {
+    let mut ar = Builder::new(Vec::new());
+    for path in ["docs/a.md", "docs/b.txt", "src/c.md"].iter() {
+        t!(ar.append_stream(path, 4, 0o644, path.as_bytes()));
+    }
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(&data[..]);
+    let mut e = t!(ar.find(|p| p.ends_with(b".txt"))).unwrap();
+    assert_eq!(&*e.path_bytes(), b"docs/b.txt");
+    let mut s = String::new();
+    t!(e.read_to_string(&mut s));
+    assert_eq!(s, "docs");
+
+    let mut ar = Archive::new(&data[..]);
+    assert!(t!(ar.contains(|p| p == b"src/c.md")));
+    let mut ar = Archive::new(&data[..]);
+    assert!(!t!(ar.contains(|p| p == b"src")));
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(&data[..]);
+    t!(ar.unpack_with(td.path(), |p| p.ends_with(b".md")));
+    assert!(td.path().join("docs/a.md").is_file());
+    assert!(!td.path().join("docs/b.txt").exists());
+    assert!(td.path().join("src/c.md").is_file());
+}
+
+#[test]
+fn try_entries_stops_at_first_error() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    let mut entries = t!(ar.try_entries());
+    let names = (&mut entries)
+        .map(|e| e.path_bytes().into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, [b"a", b"b"]);
+    assert!(entries.error().is_none());
+
+    // Corrupt the checksum of the second header.
+    let mut data = tar!("reading_files.tar").to_vec();
+    data[1024 + 148] ^= 1;
+    let mut ar = Archive::new(Cursor::new(data));
+    let mut entries = t!(ar.try_entries());
+    assert_eq!(entries.by_ref().count(), 1);
+    assert!(entries.error().is_some());
+    assert!(entries.next().is_none());
+    assert!(entries.take_error().is_some());
+    assert!(entries.error().is_none());
+}
+
+#[test]
+fn rewrite_archive() {
+    let long_path = PathBuf::from("abcd/".repeat(40) + "file");
+    let long_target = PathBuf::from("efgh/".repeat(40) + "target");
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("a", 3, 0o644, &b"aaa"[..]));
+    t!(ar.append_stream(&long_path, 4, 0o644, &b"long"[..]));
+    let mut header = Header::new_gnu();
+    header.set_mode(0o777);
+    header.set_mtime(12345);
+    header.set_size(0);
+    header.set_entry_type(EntryType::Symlink);
+    t!(ar.append_link(&mut header, "link", &long_target));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let mut out = Builder::new(Vec::new());
+    t!(ar.rewrite(&mut out, |header| {
+        header.set_mtime(0);
+        if &*header.path_bytes() == b"a" {
+            header.set_path("renamed").unwrap();
+        }
+    }));
+    let data = t!(out.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let mut entries = t!(ar.entries());
+    let mut e = t!(entries.next().unwrap());
+    assert_eq!(t!(e.path()), Path::new("renamed"));
+    assert_eq!(t!(e.header().mtime()), 0);
+    let mut s = String::new();
+    t!(e.read_to_string(&mut s));
+    assert_eq!(s, "aaa");
+    let mut e = t!(entries.next().unwrap());
+    assert_eq!(t!(e.path()), long_path);
+    assert_eq!(t!(e.header().mtime()), 0);
+    let mut s = String::new();
+    t!(e.read_to_string(&mut s));
+    assert_eq!(s, "long");
+    let e = t!(entries.next().unwrap());
+    assert_eq!(t!(e.path()), Path::new("link"));
+    assert_eq!(t!(e.header().mtime()), 0);
+    assert_eq!(t!(e.link_name()).unwrap(), long_target);
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn rewrite_sparse_archive() {
+    let mut ar = Archive::new(Cursor::new(tar!("pax_sparse-0.1.tar")));
+    let mut out = Builder::new(Vec::new());
+    t!(ar.rewrite(&mut out, |_| {}));
+    check_pax_sparse(&t!(out.into_inner()));
+
+    let mut ar = Archive::new(Cursor::new(tar!("sparse.tar")));
+    let mut out = Builder::new(Vec::new());
+    t!(ar.rewrite(&mut out, |_| {}));
+    let data = t!(out.into_inner());
+    let mut ar = Archive::new(Cursor::new(tar!("sparse.tar")));
+    let expected = t!(ar.read_all(true));
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    assert_eq!(t!(ar.read_all(true)), expected);
+}
+
+#[test]
+fn reading_entry_through_bufreader() {
+    let body = (0..100_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("a", body.len() as u64, 0o644, &body[..]));
+    t!(ar.append_stream("b", 3, 0o644, &b"bbb"[..]));
+    let data = t!(ar.into_inner());
+
+    let mut reader = LoggingReader::new(Cursor::new(&data[..]));
+    {
+        let mut ar = Archive::new(&mut reader);
+        let mut entries = t!(ar.entries());
+        let entry = t!(entries.next().unwrap());
+        let mut rdr = io::BufReader::with_capacity(4096, entry);
+        let mut buffered = Vec::new();
+        let mut chunk = [0; 7];
+        loop {
+            let n = t!(rdr.read(&mut chunk));
+            if n == 0 {
+                break;
+            }
+            buffered.extend_from_slice(&chunk[..n]);
+        }
+        assert_eq!(buffered, body);
+        drop(rdr);
+
+        let mut b = t!(entries.next().unwrap());
+        let mut s = String::new();
+        t!(b.read_to_string(&mut s));
+        assert_eq!(s, "bbb");
+    }
+    // Every byte of the archive up to the end of `b` is read exactly once.
+    assert!(reader.read_bytes <= data.len() as u64);
+}
+
+#[test]
+fn normalized_entry_paths() {
+    let paths: &[(&[u8], &[u8])] = &[
+        (b"a", b"a"),
+        (b"./a//b/./c/", b"a/b/c"),
+        (b"/abs/./path", b"/abs/path"),
+        (b"a/../b", b"a/../b"),
+        (b"./", b"."),
+        (b"//", b"/"),
+    ];
+    let mut ar = Builder::new(Vec::new());
+    for (path, _) in paths {
+        let mut header = Header::new_old();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path);
+        header.set_size(0);
+        header.set_cksum();
+        t!(ar.append(&header, io::empty()));
+    }
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(data));
+    let normalized = t!(ar.entries())
+        .map(|e| t!(e).normalized_path())
+        .collect::<Vec<_>>();
+    let expected = paths.iter().map(|p| p.1.to_vec()).collect::<Vec<_>>();
+    assert_eq!(normalized, expected);
+}
+
+#[test]
+fn hashing_reader() {
+    let data = tar!("reading_files.tar");
+    let mut ar = Archive::new(Cursor::new(data)).hashing_reader(Vec::new());
+    assert_eq!(t!(ar.read_all(false)).len(), 2);
+    let copy = ar.into_inner().hasher().clone();
+    // Everything up to and including the first terminator block is read.
+    assert_eq!(copy, &data[..copy.len()]);
+    assert!(copy.len() >= 2048);
+
+    let mut ar = Archive::new(Cursor::new(data)).hashing_reader(Vec::new());
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    t!(ar.unpack(td.path()));
+    let (mut rest, mut copy) = ar.into_inner().into_parts();
+    t!(io::copy(&mut rest, &mut copy));
+    assert_eq!(copy, data);
+
+    // Options are carried over to the wrapped archive.
+    let mut ar = Archive::new(Cursor::new(data));
+    ar.set_max_entries(Some(1));
+    let mut ar = ar.hashing_reader(io::sink());
+    assert!(ar.read_all(false).is_err());
+}
+
+#[test]
+fn verbose_listing() {
+    let mut ar = Builder::new(Vec::new());
+    let mut header = Header::new_gnu();
+    header.set_mode(0o751);
+    header.set_uid(1000);
+    header.set_gid(100);
+    t!(header.set_username("user"));
+    header.set_mtime(1234567890);
+    header.set_size(3);
+    t!(ar.append_data(&mut header, "file", &b"abc"[..]));
+    header.set_entry_type(EntryType::Symlink);
+    header.set_size(0);
+    t!(ar.append_link(&mut header, "link", "file"));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(data));
+    let list = t!(ar.verbose_list());
+    assert_eq!(list.len(), 2);
+    assert_eq!(list[0].path, Path::new("file"));
+    assert_eq!(list[0].link_name, None);
+    assert_eq!(list[0].entry_type, EntryType::Regular);
+    assert_eq!(list[0].mode, 0o751);
+    assert_eq!(list[0].uid, 1000);
+    assert_eq!(list[0].gid, 100);
+    assert_eq!(list[0].username.as_deref(), Some("user"));
+    assert_eq!(list[0].groupname, None);
+    assert_eq!(list[0].size, 3);
+    assert_eq!(list[0].mtime, 1234567890);
+    assert_eq!(list[1].path, Path::new("link"));
+    assert_eq!(list[1].link_name.as_deref(), Some(Path::new("file")));
+    assert_eq!(list[1].entry_type, EntryType::Symlink);
+    assert_eq!(list[1].size, 0);
+}
+
+#[test]
+fn verbose_listing_pax() {
+    let mut ar = Archive::new(Cursor::new(tar!("pax.tar")));
+    let list = t!(ar.verbose_list());
+    assert_eq!(list[0].path, Path::new("Cargo.toml"));
+    assert_eq!(list[0].mtime, 1453146164);
+    assert_eq!(list[0].uid, 1000);
+
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_pax_extensions([
+        ("mtime", &b"8589934592.5"[..]),
+        ("uid", &b"3000000000"[..]),
+        ("gid", &b"70000"[..]),
+        ("uname", &b"pax-user"[..]),
+    ]));
+    let mut header = Header::new_ustar();
+    header.set_mode(0o644);
+    header.set_uid(1);
+    header.set_gid(2);
+    t!(header.set_username("user"));
+    header.set_mtime(3);
+    header.set_size(0);
+    t!(ar.append_data(&mut header, "file", &[][..]));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(data));
+    let list = t!(ar.verbose_list());
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].mtime, 8589934592);
+    assert_eq!(list[0].uid, 3000000000);
+    assert_eq!(list[0].gid, 70000);
+    assert_eq!(list[0].username.as_deref(), Some("pax-user"));
+}
+
+#[test]
+fn extract_newer_than() {
+    let mut ar = Builder::new(Vec::new());
+    for (name, mtime) in [("old", 100), ("same", 200), ("new", 300)].iter() {
+        let mut header = Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(*mtime);
+        header.set_size(1);
+        t!(ar.append_data(&mut header, name, &b"x"[..]));
+    }
+    let data = t!(ar.into_inner());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_extract_newer_than(Some(200));
+    t!(ar.unpack(td.path()));
+    assert!(!td.path().join("old").exists());
+    assert!(!td.path().join("same").exists());
+    assert!(td.path().join("new").exists());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_extract_newer_than(Some(100));
+    let results = t!(ar.unpack_verified(td.path(), false));
+    assert_eq!(results.len(), 3);
+    assert!(!td.path().join("old").exists());
+    assert!(td.path().join("same").exists());
+
+    // A pax mtime record takes precedence over the header.
+    let mut ar = Builder::new(Vec::new());
+    for (name, mtime, pax_mtime) in [("stale", 300, "100.5"), ("fresh", 200, "200.5")].iter() {
+        t!(ar.append_pax_extensions([("mtime", pax_mtime.as_bytes())]));
+        let mut header = Header::new_ustar();
+        header.set_mode(0o644);
+        header.set_mtime(*mtime);
+        header.set_size(1);
+        t!(ar.append_data(&mut header, name, &b"x"[..]));
+    }
+    let data = t!(ar.into_inner());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_extract_newer_than(Some(200));
+    t!(ar.unpack(td.path()));
+    assert!(!td.path().join("stale").exists());
+    assert!(td.path().join("fresh").exists());
+}
+
+#[test]
+fn atomic_unpack() {
+    let mut ar = Builder::new(Vec::new());
+    for name in ["dir/a", "dir/b"].iter() {
+        let mut header = Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(1);
+        t!(ar.append_data(&mut header, name, &b"x"[..]));
+    }
+    let data = t!(ar.into_inner());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let dst = td.path().join("out");
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_atomic_unpack(true);
+    t!(ar.unpack(&dst));
+    assert!(dst.join("dir/a").is_file());
+    assert!(dst.join("dir/b").is_file());
+    assert_eq!(t!(fs::read_dir(td.path())).count(), 1);
+
+    // The destination must not exist yet.
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_atomic_unpack(true);
+    let err = ar.unpack(&dst).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+    // Nothing is left behind when an entry fails halfway through.
+    let mut bad = data.clone();
+    bad[1024 + 148] ^= 1;
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(&bad[..]));
+    ar.set_atomic_unpack(true);
+    assert!(ar.unpack(td.path().join("out")).is_err());
+    assert_eq!(t!(fs::read_dir(td.path())).count(), 0);
+
+    // A destination which appears while unpacking is left alone.
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let dst = td.path().join("out");
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_atomic_unpack(true);
+    let err = ar
+        .unpack_with(&dst, |_| {
+            if !dst.exists() {
+                t!(fs::create_dir(&dst));
+                t!(fs::write(dst.join("theirs"), b"theirs"));
+            }
+            true
+        })
+        .unwrap_err();
+    assert!(err.to_string().contains("into place"), "{}", err);
+    assert_eq!(t!(fs::read_to_string(dst.join("theirs"))), "theirs");
+    assert_eq!(t!(fs::read_dir(&dst)).count(), 1);
+    assert_eq!(t!(fs::read_dir(td.path())).count(), 1);
+
+    // Even an empty directory which appears while unpacking is not replaced.
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let dst = td.path().join("out");
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    ar.set_atomic_unpack(true);
+    let err = ar
+        .unpack_with(&dst, |_| {
+            if !dst.exists() {
+                t!(fs::create_dir(&dst));
+            }
+            true
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(t!(fs::read_dir(&dst)).count(), 0);
+    assert_eq!(t!(fs::read_dir(td.path())).count(), 1);
+}
+
+#[test]
+fn cancel_flag() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let flag = Arc::new(AtomicBool::new(false));
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    ar.set_cancel_flag(Some(flag.clone()));
+    let mut entries = t!(ar.entries());
+    let mut a = t!(entries.next().unwrap());
+    let mut s = String::new();
+    t!(a.read_to_string(&mut s));
+    flag.store(true, Ordering::Relaxed);
+    let err = entries.next().unwrap().err().unwrap();
+    assert_eq!(err.to_string(), "operation cancelled");
+    assert!(entries.next().is_none());
+
+    // The flag is also checked while copying contents during unpacking.
+    struct CancelAfter<R> {
+        inner: R,
+        left: usize,
+        flag: Arc<AtomicBool>,
+    }
+    impl<R: Read> Read for CancelAfter<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let max = self.left.min(buf.len());
+            let n = self.inner.read(&mut buf[..max])?;
+            self.left -= n;
+            if self.left == 0 {
+                self.flag.store(true, Ordering::Relaxed);
+            }
+            Ok(n)
+        }
+    }
+    let mut ar = Builder::new(Vec::new());
+    let mut header = Header::new_gnu();
+    header.set_mode(0o644);
+    header.set_size(4096);
+    t!(ar.append_data(&mut header, "big", &[b'x'; 4096][..]));
+    let data = t!(ar.into_inner());
+
+    let flag = Arc::new(AtomicBool::new(false));
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(CancelAfter {
+        inner: &data[..],
+        left: 1024,
+        flag: flag.clone(),
+    });
+    ar.set_cancel_flag(Some(flag));
+    let err = ar.unpack(td.path()).unwrap_err();
+    let mut cause: Option<&dyn std::error::Error> = Some(&err);
+    while let Some(e) = cause {
+        if e.to_string() == "operation cancelled" {
+            break;
+        }
+        cause = e.source();
+    }
+    assert!(cause.is_some(), "{}", err);
+}
+
 #[test]
-fn skipping_entries_with_seek() {
-    let mut reader = LoggingReader::new(Cursor::new(tar!("reading_files.tar")));
-    let mut ar_reader = Archive::new(&mut reader);
-    let files: Vec<_> = t!(ar_reader.entries())
-        .map(|entry| entry.unwrap().path().unwrap().to_path_buf())
-        .collect();
+fn raw_entry_positions() {
+    let long_path = PathBuf::from("abcd/".repeat(40) + "file");
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("a", 3, 0o644, &b"aaa"[..]));
+    t!(ar.append_stream(&long_path, 4, 0o644, &b"long"[..]));
+    let data = t!(ar.into_inner());
 
-    let mut seekable_reader = LoggingReader::new(Cursor::new(tar!("reading_files.tar")));
-    let mut ar_seekable_reader = Archive::new(&mut seekable_reader);
-    let files_seekable: Vec<_> = t!(ar_seekable_reader.entries_with_seek())
-        .map(|entry| entry.unwrap().path().unwrap().to_path_buf())
-        .collect();
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    let mut positions = Vec::new();
+    for entry in t!(ar.entries()) {
+        let e = t!(entry);
+        let header = e.raw_header_position() as usize;
+        let file = e.raw_file_position() as usize;
+        assert_eq!(&data[header..header + 512], e.header().as_bytes());
+        positions.push((header, file, file + e.size() as usize));
+    }
+    // The long name is stored in its own entry before the real header.
+    assert_eq!(positions, [(0, 512, 515), (2048, 2560, 2564)]);
+    assert_eq!(&data[512..515], b"aaa");
+    assert_eq!(&data[2560..2564], b"long");
+}
 
-    assert!(files == files_seekable);
-    assert!(seekable_reader.read_bytes < reader.read_bytes);
+#[test]
+fn reading_names() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    let names = t!(t!(ar.names()).collect::<io::Result<Vec<_>>>());
+    assert_eq!(names, [b"a", b"b"]);
+
+    let mut ar = Archive::new(Cursor::new(tar!("7z_long_path.tar")));
+    let names = t!(t!(ar.names_with_seek()).collect::<io::Result<Vec<_>>>());
+    let mut ar = Archive::new(Cursor::new(tar!("7z_long_path.tar")));
+    let paths = t!(ar.entries())
+        .map(|e| t!(e).path_bytes().into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, paths);
+}
+
+#[test]
+fn reading_unterminated_numeric_fields() {
+    // The first header fills its size and checksum fields with digits and
+    // leaves no room for a terminator.
+    let mut ar = Archive::new(Cursor::new(tar!("full_numeric_fields.tar")));
+    let files = t!(ar.read_all(false));
+    assert_eq!(files[0], (b"full".to_vec(), b"hello".to_vec()));
+    assert_eq!(files[1], (b"next".to_vec(), b"world\n".to_vec()));
+}
+
+#[test]
+fn reading_space_padded_bodies() {
+    // Headers are located from the size field alone, so bodies padded with
+    // something other than NULs still leave the terminator where expected.
+    let mut ar = Archive::new(Cursor::new(tar!("space_padded.tar")));
+    let files = t!(ar.read_all(true));
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0], (b"a".to_vec(), b"a\na\na\n".to_vec()));
+    assert_eq!(files[1], (b"b".to_vec(), b"bbbbb".to_vec()));
+
+    let mut ar = Archive::new(Cursor::new(tar!("space_padded.tar")));
+    assert_eq!(t!(ar.terminator_offset()), Some(2048));
+}
+
+#[test]
+fn reading_padding_bytes() {
+    let mut ar = Archive::new(Cursor::new(tar!("space_padded.tar")));
+    let mut entries = t!(ar.entries_with_seek());
+    let mut a = t!(entries.next().unwrap());
+    let mut buf = [0; 2];
+    t!(a.read_exact(&mut buf));
+    assert_eq!(t!(a.padding_bytes()), vec![b' '; 506]);
+    assert!(t!(a.padding_bytes()).is_empty());
+    let mut b = t!(entries.next().unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+    assert_eq!(t!(b.padding_bytes()), vec![b' '; 507]);
+    assert!(entries.next().is_none());
+
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    let mut entries = t!(ar.entries());
+    let mut a = t!(entries.next().unwrap());
+    let padding = t!(a.padding_bytes());
+    assert_eq!(padding.len(), 512 - a.size() as usize);
+    assert!(padding.iter().all(|b| *b == 0));
+    assert_eq!(&*t!(entries.next().unwrap()).path_bytes(), b"b");
+}
+
+#[test]
+fn reading_last_entry() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    {
+        let mut b = t!(ar.last()).unwrap();
+        assert_eq!(&*b.path_bytes(), b"b");
+        let mut s = String::new();
+        t!(b.read_to_string(&mut s));
+        assert_eq!(s, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+    }
+
+    // The long name of the final entry is taken into account.
+    let long_path = PathBuf::from("abcd/".repeat(40) + "manifest");
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_stream("a", 1, 0o644, &b"a"[..]));
+    t!(ar.append_stream(&long_path, 4, 0o644, &b"last"[..]));
+    let data = t!(ar.into_inner());
+    let mut ar = Archive::new(Cursor::new(data));
+    let mut e = t!(ar.last()).unwrap();
+    assert_eq!(t!(e.path()), long_path);
+    let mut s = String::new();
+    t!(e.read_to_string(&mut s));
+    assert_eq!(s, "last");
+
+    let mut ar = Archive::new(Cursor::new(vec![0; 1024]));
+    assert!(t!(ar.last()).is_none());
+}
+
+#[test]
+fn scanning_for_entries() {
+    let mut ar = Builder::new(Vec::new());
+    let big = vec![b'x'; 1 << 20];
+    t!(ar.append_stream("haystack1", big.len() as u64, 0o644, &big[..]));
+    t!(ar.append_stream("needle", 6, 0o644, &b"needle"[..]));
+    t!(ar.append_stream("haystack2", big.len() as u64, 0o644, &big[..]));
+    let data = t!(ar.into_inner());
+
+    let mut reader = LoggingReader::new(Cursor::new(data));
+    let mut ar = Archive::new(&mut reader);
+    let mut found = Vec::new();
+    for entry in t!(ar.scan(|e| &*e.path_bytes() == b"needle")) {
+        let mut entry = t!(entry);
+        let mut s = String::new();
+        t!(entry.read_to_string(&mut s));
+        found.push(s);
+    }
+    assert_eq!(found, ["needle"]);
+    assert!(reader.read_bytes < 8 * 1024);
+}
+
+#[test]
+fn count_matching_entries() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    assert_eq!(t!(ar.count_matching(|_| true)), 2);
+
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    assert_eq!(t!(ar.count_matching(|e| &*e.path_bytes() == b"b")), 1);
+
+    let mut ar = Archive::new(Cursor::new(tar!("directory.tar")));
+    let dirs = t!(ar.count_matching(|e| e.header().entry_type().is_dir()));
+    assert_eq!(dirs, 2);
+
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    ar.set_max_entries(Some(1));
+    assert!(ar.count_matching(|_| false).is_err());
+}
+
+#[test]
+fn max_entries() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    ar.set_max_entries(Some(2));
+    assert_eq!(t!(t!(ar.names()).collect::<io::Result<Vec<_>>>()).len(), 2);
+
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    ar.set_max_entries(Some(1));
+    let mut names = t!(ar.names());
+    assert_eq!(t!(names.next().unwrap()), b"a");
+    assert!(names.next().unwrap().is_err());
+    assert!(names.next().is_none());
+
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    ar.set_max_entries(Some(1));
+    assert!(ar.verify().is_err());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    ar.set_max_entries(Some(0));
+    assert!(ar.unpack(td.path()).is_err());
+}
+
+#[test]
+fn terminator_offset() {
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    assert_eq!(t!(ar.terminator_offset()), Some(2048));
+
+    let mut ar = Builder::new(Vec::new());
+    let mut header = Header::new_gnu();
+    header.set_size(3);
+    header.set_cksum();
+    t!(ar.append_data(&mut header, "foo", &b"foo"[..]));
+    let mut data = t!(ar.into_inner());
+    let mut ar = Archive::new(Cursor::new(&data));
+    assert_eq!(t!(ar.terminator_offset()), Some(1024));
+
+    data.truncate(1024);
+    let mut ar = Archive::new(Cursor::new(&data));
+    assert_eq!(t!(ar.terminator_offset()), None);
+}
+
+#[test]
+fn open_scanning_skips_junk_prefix() {
+    let mut data = vec![b'x'; 1024];
+    data.extend_from_slice(tar!("reading_files.tar"));
+
+    let mut ar = Archive::new(Cursor::new(&data));
+    assert!(ar.entries().unwrap().next().unwrap().is_err());
+
+    let mut ar = t!(Archive::open_scanning(Cursor::new(&data)));
+    assert_eq!(ar.start_offset(), 1024);
+    let mut entries = t!(ar.entries_with_seek());
+    let mut a = t!(entries.next().unwrap());
+    assert_eq!(&*a.path_bytes(), b"a");
+    assert_eq!(a.raw_header_position(), 1024);
+    let mut s = String::new();
+    t!(a.read_to_string(&mut s));
+    assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+    let b = t!(entries.next().unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+    assert!(entries.next().is_none());
+
+    let ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    assert_eq!(ar.start_offset(), 0);
+    assert!(Archive::open_scanning(Cursor::new(vec![b'x'; 1024])).is_err());
+}
+
+#[test]
+fn open_scanning_skips_unaligned_prefix() {
+    let mut data = b"#!/bin/sh\nexec tar -x \"$0\"\nexit 1\n\0\0\0".to_vec();
+    assert_eq!(data.len(), 37);
+    data.extend_from_slice(tar!("reading_files.tar"));
+
+    let mut ar = t!(Archive::open_scanning(Cursor::new(&data)));
+    assert_eq!(ar.start_offset(), 37);
+    let mut entries = t!(ar.entries_with_seek());
+    let mut a = t!(entries.next().unwrap());
+    assert_eq!(&*a.path_bytes(), b"a");
+    assert_eq!(a.raw_header_position(), 37);
+    let mut s = String::new();
+    t!(a.read_to_string(&mut s));
+    assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+    let b = t!(entries.next().unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+    assert!(entries.next().is_none());
+
+    // The prefix may also end partway through the first chunk read.
+    let mut data = vec![b'x'; 5000];
+    data.extend_from_slice(tar!("reading_files.tar"));
+    let ar = t!(Archive::open_scanning(Cursor::new(&data)));
+    assert_eq!(ar.start_offset(), 5000);
 }
 
 fn check_dirtree(td: &TempDir) {
@@ -482,6 +2001,46 @@ fn append_dir_all_blank_dest() {
         .unwrap_or(false));
 }
 
+#[test]
+fn skip_duplicate_dirs() {
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let base_dir = td.path().join("base");
+    let sub_dir = base_dir.join("sub");
+    t!(fs::create_dir_all(&sub_dir));
+    t!(t!(File::create(sub_dir.join("file"))).write_all(b"file"));
+
+    let dirs = |skip: bool| {
+        let mut ar = Builder::new(Vec::new());
+        ar.skip_duplicate_dirs(skip);
+        t!(ar.append_dir_all("x", &base_dir));
+        t!(ar.append_dir("x/sub", &sub_dir));
+        t!(ar.append_path_with_name(&sub_dir, "./x/sub/"));
+        let data = t!(ar.into_inner());
+
+        let mut ar = Archive::new(&data[..]);
+        t!(ar.entries())
+            .map(|e| t!(e))
+            .filter(|e| e.header().entry_type().is_dir())
+            .count()
+    };
+    assert_eq!(dirs(false), 4);
+    assert_eq!(dirs(true), 2);
+
+    // A directory which failed to be appended can be retried.
+    let missing = td.path().join("missing");
+    let mut ar = Builder::new(Vec::new());
+    ar.skip_duplicate_dirs(true);
+    assert!(ar.append_dir("y", &missing).is_err());
+    t!(fs::create_dir(&missing));
+    t!(ar.append_dir("y", &missing));
+    let data = t!(ar.into_inner());
+    let mut ar = Archive::new(&data[..]);
+    let names = t!(ar.entries())
+        .map(|e| t!(e).path_bytes().into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, [b"y".to_vec()]);
+}
+
 #[test]
 fn append_dir_all_does_not_work_on_non_directory() {
     let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
@@ -686,6 +2245,21 @@ fn empty_filename() {
     assert!(ar.unpack(td.path()).is_ok());
 }
 
+#[test]
+fn reject_empty_paths() {
+    let mut ar = Archive::new(Cursor::new(tar!("empty_filename.tar")));
+    let entry = t!(t!(ar.entries()).next().unwrap());
+    assert!(entry.path_bytes().is_empty());
+
+    let mut ar = Archive::new(Cursor::new(tar!("empty_filename.tar")));
+    ar.set_reject_empty_paths(true);
+    assert!(t!(ar.entries()).next().unwrap().is_err());
+
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    ar.set_reject_empty_paths(true);
+    assert_eq!(t!(ar.entries()).count(), 2);
+}
+
 #[test]
 fn file_times() {
     let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
@@ -702,6 +2276,53 @@ fn file_times() {
     assert_eq!(atime.nanoseconds(), 0);
 }
 
+#[test]
+fn entry_modified() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let mut ar = Builder::new(Vec::new());
+    for (i, record) in ["30 mtime=1234567890.123456789\n", "15 mtime=-1.25\n"]
+        .iter()
+        .enumerate()
+    {
+        let mut h = Header::new_ustar();
+        t!(h.set_path("pax"));
+        h.set_size(record.len() as u64);
+        h.set_entry_type(EntryType::XHeader);
+        h.set_cksum();
+        t!(ar.append(&h, record.as_bytes()));
+
+        let mut h = Header::new_ustar();
+        h.set_size(0);
+        h.set_mode(0o644);
+        h.set_mtime(1234567890);
+        t!(ar.append_data(&mut h, format!("file{}", i), io::empty()));
+    }
+    t!(ar.append_stream("plain", 0, 0o644, io::empty()));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(&data[..]);
+    let modified = t!(ar.entries())
+        .map(|e| t!(t!(e).modified()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        modified[0],
+        UNIX_EPOCH + Duration::new(1234567890, 123456789)
+    );
+    assert_eq!(modified[1], UNIX_EPOCH - Duration::from_millis(1250));
+    let plain = t!(modified[2].duration_since(UNIX_EPOCH));
+    assert_eq!(plain.subsec_nanos(), 0);
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(&data[..]);
+    t!(ar.unpack(td.path()));
+    let meta = t!(fs::metadata(td.path().join("file0")));
+    // Some filesystems only store the time to the nearest 100ns.
+    let unpacked = t!(t!(meta.modified()).duration_since(UNIX_EPOCH));
+    assert_eq!(unpacked.as_secs(), 1234567890);
+    assert_eq!(unpacked.subsec_micros(), 123456);
+}
+
 #[test]
 fn zero_file_times() {
     let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
@@ -868,6 +2489,143 @@ fn pax_linkpath() {
     assert!(link_name.ends_with("ccccccccccccccc"));
 }
 
+#[test]
+fn pax_linkpath_round_trip() {
+    let target = "t/".repeat(100) + "target";
+    let mut ar = Builder::new(Vec::new());
+    t!(ar.append_pax_extensions([("linkpath", target.as_bytes())]));
+    let mut header = Header::new_ustar();
+    header.set_entry_type(EntryType::Symlink);
+    t!(header.set_path("link"));
+    t!(header.set_link_name("short"));
+    header.set_size(0);
+    header.set_cksum();
+    t!(ar.append(&header, io::empty()));
+    t!(ar.append_pax_extensions(None));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(&data[..]);
+    let mut entries = t!(ar.entries());
+    let link = t!(entries.next().unwrap());
+    assert_eq!(&*t!(link.path()), Path::new("link"));
+    assert_eq!(t!(link.link_name()).unwrap(), Path::new(&target));
+    assert_eq!(&*link.header().link_name_bytes().unwrap(), b"short");
+    assert!(entries.next().is_none());
+
+    let mut ar = Builder::new(Vec::new());
+    ar.format(HeaderFormat::V7);
+    assert!(ar
+        .append_pax_extensions([("linkpath", target.as_bytes())])
+        .is_err());
+}
+
+#[test]
+fn gnu_dumpdir() {
+    let mut b = Builder::new(Vec::<u8>::new());
+    let mut h = Header::new_gnu();
+    t!(h.set_path("dumped/"));
+    h.set_size(7);
+    h.set_mode(0o755);
+    h.set_entry_type(EntryType::new(b'D'));
+    h.set_cksum();
+    t!(b.append(&h, "Yfile\0\0".as_bytes()));
+    let contents = t!(b.into_inner());
+
+    let mut a = Archive::new(&contents[..]);
+    let mut e = t!(t!(a.entries()).next().unwrap());
+    assert_eq!(e.header().entry_type(), EntryType::GNUDumpDir);
+    assert!(e.header().entry_type().is_gnu_dumpdir());
+    assert_eq!(e.header().entry_type().as_byte(), b'D');
+    let mut body = Vec::new();
+    t!(e.read_to_end(&mut body));
+    assert_eq!(body, b"Yfile\0\0");
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut a = Archive::new(&contents[..]);
+    t!(a.unpack(td.path()));
+    assert!(!td.path().join("dumped").exists());
+}
+
+#[test]
+fn max_path_len() {
+    let mut ar = Archive::new(Cursor::new(tar!("long_gnu_name.tar")));
+    let err = t!(ar.entries()).next().unwrap().err().unwrap();
+    assert!(err.to_string().contains("maximum path length"), "{}", err);
+
+    let mut ar = Archive::new(Cursor::new(tar!("long_gnu_name.tar")));
+    ar.set_max_path_len(128 * 1024);
+    let e = t!(t!(ar.entries()).next().unwrap());
+    assert_eq!(e.path_bytes().len(), 70354);
+
+    // The final path is checked too, whatever it was built from.
+    let mut ar = Archive::new(Cursor::new(tar!("reading_files.tar")));
+    ar.set_max_path_len(0);
+    let err = t!(ar.entries()).next().unwrap().err().unwrap();
+    assert!(
+        err.to_string().contains("longer than the maximum"),
+        "{}",
+        err
+    );
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(Cursor::new(tar!("long_gnu_name.tar")));
+    assert!(ar.unpack(td.path()).is_err());
+}
+
+#[test]
+fn max_path_len_pax() {
+    let long = "a".repeat(200);
+    let mut b = Builder::new(Vec::new());
+    t!(b.append_pax_extensions([("path", long.as_bytes())]));
+    let mut h = Header::new_gnu();
+    t!(h.set_path("short"));
+    h.set_size(0);
+    h.set_cksum();
+    t!(b.append(&h, &[][..]));
+    let data = t!(b.into_inner());
+
+    let mut ar = Archive::new(&data[..]);
+    ar.set_max_path_len(100);
+    let err = t!(ar.entries()).next().unwrap().err().unwrap();
+    assert!(err.to_string().contains("pax path record"), "{}", err);
+
+    // A GNU long name takes precedence over the pax path, but the oversized
+    // pax record is still rejected.
+    let mut b = Builder::new(Vec::new());
+    t!(b.append_pax_extensions([("path", long.as_bytes())]));
+    let mut h = Header::new_gnu();
+    t!(h.set_path("././@LongLink"));
+    h.set_size(6);
+    h.set_entry_type(EntryType::new(b'L'));
+    h.set_cksum();
+    t!(b.append(&h, &b"short\0"[..]));
+    let mut h = Header::new_gnu();
+    t!(h.set_path("short"));
+    h.set_size(0);
+    h.set_cksum();
+    t!(b.append(&h, &[][..]));
+    let data = t!(b.into_inner());
+
+    let mut ar = Archive::new(&data[..]);
+    ar.set_max_path_len(100);
+    assert!(t!(ar.entries()).next().unwrap().is_err());
+
+    // The size of the extension entry is checked before it is read.
+    let huge = "x".repeat(80 * 1024);
+    let mut b = Builder::new(Vec::new());
+    t!(b.append_pax_extensions([("comment", huge.as_bytes())]));
+    t!(b.append(&h, &[][..]));
+    let data = t!(b.into_inner());
+
+    let mut ar = Archive::new(&data[..]);
+    ar.set_max_path_len(100);
+    let err = t!(ar.entries()).next().unwrap().err().unwrap();
+    assert!(err.to_string().contains("pax extensions entry"), "{}", err);
+
+    let mut ar = Archive::new(&data[..]);
+    let e = t!(t!(ar.entries()).next().unwrap());
+    assert_eq!(&*e.path_bytes(), b"short");
+}
+
 #[test]
 fn long_name_trailing_nul() {
     let mut b = Builder::new(Vec::<u8>::new());
@@ -984,6 +2742,65 @@ fn encoded_long_name_has_trailing_nul() {
     assert!(header_name.starts_with(b"././@LongLink\x00"));
 }
 
+#[test]
+fn entries_with_slices() {
+    let data = tar!("reading_files.tar");
+    let mut ar = Archive::new(Cursor::new(data));
+    let mut entries = t!(ar.entries_with_slices());
+    let (mut a, body) = t!(entries.next().unwrap());
+    assert_eq!(body.unwrap(), "a\n".repeat(11).as_bytes());
+    let mut byte = [0; 1];
+    t!(a.read_exact(&mut byte));
+    let (_, body) = t!(entries.next().unwrap());
+    assert_eq!(body.unwrap(), "b\n".repeat(11).as_bytes());
+
+    // Slices are relative to where the archive starts in the cursor.
+    let mut prefixed = b"prefix".to_vec();
+    prefixed.extend_from_slice(data);
+    let mut cursor = Cursor::new(&prefixed[..]);
+    cursor.set_position(6);
+    let mut ar = Archive::new(cursor);
+    let (_, body) = t!(t!(ar.entries_with_slices()).next().unwrap());
+    assert_eq!(body.unwrap(), "a\n".repeat(11).as_bytes());
+
+    // Hard links have no contents even if their header lists a size.
+    let mut ar = Archive::new(Cursor::new(tar!("hardlink_with_size.tar")));
+    let bodies = t!(ar.entries_with_slices())
+        .map(|entry| {
+            let (entry, body) = t!(entry);
+            (t!(entry.path()).into_owned(), body.unwrap())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(bodies.len(), 3);
+    assert_eq!(bodies[1].0, Path::new("b"));
+    assert_eq!(bodies[1].1, b"");
+    assert_eq!(bodies[2].0, Path::new("c"));
+    assert_eq!(bodies[2].1, b"world\n");
+
+    // Sparse entries have no contiguous contents, and the contents of every
+    // other entry match what is read.
+    for data in [
+        tar!("sparse.tar"),
+        tar!("pax_sparse-0.1.tar"),
+        tar!("pax_sparse-1.0.tar"),
+    ] {
+        let mut ar = Archive::new(Cursor::new(data));
+        let mut sparse = 0;
+        for entry in t!(ar.entries_with_slices()) {
+            let (mut entry, body) = t!(entry);
+            match body {
+                Some(body) => {
+                    let mut read = Vec::new();
+                    t!(entry.read_to_end(&mut read));
+                    assert_eq!(body, &read[..]);
+                }
+                None => sparse += 1,
+            }
+        }
+        assert!(sparse > 0);
+    }
+}
+
 #[test]
 fn reading_sparse() {
     let rdr = Cursor::new(tar!("sparse.tar"));
@@ -1102,6 +2919,63 @@ fn pax_sparse() {
     assert!(s[5..].chars().all(|x| x == '\u{0}'));
 }
 
+fn check_pax_sparse(tar: &[u8]) {
+    let mut ar = Archive::new(Cursor::new(tar));
+    let mut entries = t!(ar.entries());
+
+    let mut a = t!(entries.next().unwrap());
+    assert_eq!(&*a.path_bytes(), b"sparse_begin.txt");
+    assert_eq!(a.size(), 16384);
+    let mut s = Vec::new();
+    t!(a.read_to_end(&mut s));
+    assert_eq!(s.len(), 16384);
+    assert_eq!(&s[..5], b"test\n");
+    assert!(s[5..].iter().all(|x| *x == 0));
+
+    let mut a = t!(entries.next().unwrap());
+    assert_eq!(&*a.path_bytes(), b"sparse_end.txt");
+    assert_eq!(a.size(), 12297);
+    let mut s = Vec::new();
+    t!(a.read_to_end(&mut s));
+    assert!(s[..12288].iter().all(|x| *x == 0));
+    assert_eq!(&s[12288..], b"test_end\n");
+
+    let mut a = t!(entries.next().unwrap());
+    assert_eq!(&*a.path_bytes(), b"sparse_mid.txt");
+    assert_eq!(a.size(), 20480);
+    let mut s = Vec::new();
+    t!(a.read_to_end(&mut s));
+    assert_eq!(s.len(), 20480);
+    assert_eq!(&s[..6], b"start\n");
+    assert!(s[6..8192].iter().all(|x| *x == 0));
+    assert_eq!(&s[8192..8199], b"middle\n");
+    assert!(s[8199..].iter().all(|x| *x == 0));
+
+    assert!(entries.next().is_none());
+
+    let mut ar = Archive::new(Cursor::new(tar));
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    t!(ar.unpack(td.path()));
+    let s = t!(fs::read(td.path().join("sparse_mid.txt")));
+    assert_eq!(s.len(), 20480);
+    assert_eq!(&s[8192..8199], b"middle\n");
+}
+
+#[test]
+fn pax_sparse_0_0() {
+    check_pax_sparse(tar!("pax_sparse-0.0.tar"));
+}
+
+#[test]
+fn pax_sparse_0_1() {
+    check_pax_sparse(tar!("pax_sparse-0.1.tar"));
+}
+
+#[test]
+fn pax_sparse_1_0() {
+    check_pax_sparse(tar!("pax_sparse-1.0.tar"));
+}
+
 #[test]
 fn path_separators() {
     let mut ar = Builder::new(Vec::new());
@@ -1270,6 +3144,41 @@ fn tar_directory_containing_symlink_to_directory() {
     ar.finish().unwrap();
 }
 
+#[test]
+#[cfg(unix)]
+fn append_dir_all_symlinks_round_trip() {
+    use std::os::unix::fs::symlink;
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let src = td.path().join("src");
+    t!(fs::create_dir(&src));
+    let long_target = PathBuf::from("target/".repeat(30) + "file");
+    t!(symlink("plain", src.join("short")));
+    t!(symlink(&long_target, src.join("long")));
+
+    let mut ar = Builder::new(Vec::new());
+    ar.follow_symlinks(false);
+    t!(ar.append_dir_all("tree", &src));
+    let data = t!(ar.into_inner());
+
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    for entry in t!(ar.entries()) {
+        let entry = t!(entry);
+        if entry.header().entry_type().is_symlink() {
+            assert_eq!(t!(entry.header().size()), 0);
+        }
+    }
+
+    let dst = td.path().join("dst");
+    let mut ar = Archive::new(Cursor::new(&data[..]));
+    t!(ar.unpack(&dst));
+    assert_eq!(
+        t!(fs::read_link(dst.join("tree/short"))),
+        Path::new("plain")
+    );
+    assert_eq!(t!(fs::read_link(dst.join("tree/long"))), long_target);
+}
+
 #[test]
 fn long_path() {
     let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
@@ -1456,3 +3365,33 @@ fn ownership_preserving() {
         assert!(ar.unpack(td.path()).is_err());
     }
 }
+
+#[test]
+#[cfg(unix)]
+fn unpack_masks_file_type_bits_from_mode() {
+    use std::os::unix::prelude::*;
+
+    let mut ar = Builder::new(Vec::new());
+    let mut header = Header::new_gnu();
+    header.set_size(0);
+    header.set_entry_type(EntryType::Regular);
+    // a full `st_mode` of a setuid regular file
+    header.set_mode(0o104755);
+    header.set_cksum();
+    t!(ar.append_data(&mut header, "a", io::empty()));
+    let data = t!(ar.into_inner());
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(&data[..]);
+    ar.set_preserve_permissions(true);
+    t!(ar.unpack(td.path()));
+    let md = t!(fs::metadata(td.path().join("a")));
+    assert!(md.is_file());
+    assert_eq!(md.permissions().mode() & 0o7777, 0o4755);
+
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let mut ar = Archive::new(&data[..]);
+    t!(ar.unpack(td.path()));
+    let md = t!(fs::metadata(td.path().join("a")));
+    assert_eq!(md.permissions().mode() & 0o7777, 0o755);
+}
@@ -5,7 +5,7 @@ use std::{iter, mem, thread, time};
 
 use tempfile::Builder;
 
-use tar::{GnuHeader, Header, HeaderMode};
+use tar::{EntryType, GnuHeader, Header, HeaderMode};
 
 #[test]
 fn default_gnu() {
@@ -123,6 +123,26 @@ fn dev_major_minor() {
     assert!(h.set_device_minor(1).is_err());
 }
 
+#[test]
+fn device_headers() {
+    let h = t!(Header::new_char_device(4, 64));
+    assert_eq!(h.entry_type(), EntryType::Char);
+    assert_eq!(t!(h.size()), 0);
+    assert_eq!(t!(h.device_major()), Some(4));
+    assert_eq!(t!(h.device_minor()), Some(64));
+
+    let h = t!(Header::new_block_device(8, 0o7777777));
+    assert_eq!(h.entry_type(), EntryType::Block);
+    assert_eq!(t!(h.device_major()), Some(8));
+    assert_eq!(t!(h.device_minor()), Some(0o7777777));
+
+    assert!(Header::new_char_device(0o10000000, 0).is_err());
+    assert!(Header::new_block_device(0, 0o10000000).is_err());
+    let mut h = Header::new_ustar();
+    assert!(h.set_device_major(u32::MAX).is_err());
+    assert!(h.set_device_minor(u32::MAX).is_err());
+}
+
 #[test]
 fn set_path() {
     let mut h = Header::new_gnu();
@@ -237,6 +257,25 @@ fn extended_numeric_format() {
     assert_eq!(h.as_header().mtime().unwrap(), 0x0123456789abcdef);
 }
 
+#[test]
+fn unterminated_numeric_fields() {
+    let mut h: GnuHeader = unsafe { mem::zeroed() };
+    h.size = *b"777777777777";
+    assert_eq!(h.as_header().entry_size().unwrap(), 0o777777777777);
+    h.size = *b"   000000052";
+    assert_eq!(h.as_header().entry_size().unwrap(), 42);
+
+    h.cksum = *b"00012345";
+    assert_eq!(h.as_header().cksum().unwrap(), 0o12345);
+    h.cksum = *b"012345\0 ";
+    assert_eq!(h.as_header().cksum().unwrap(), 0o12345);
+    h.cksum = *b" 12345 \0";
+    assert_eq!(h.as_header().cksum().unwrap(), 0o12345);
+
+    h.mode = *b"00000644";
+    assert_eq!(h.as_header().mode().unwrap(), 0o644);
+}
+
 #[test]
 fn byte_slice_conversion() {
     let h = Header::new_gnu();